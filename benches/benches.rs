@@ -12,11 +12,11 @@ fn load_from_core<P: AsRef<Path>>(file: P) -> (run::Book, run::Net) {
   let code = fs::read_to_string(file).unwrap();
   let (size, code) = extract_size(&code);
 
-  let book = ast::do_parse_book(code);
+  let book = ast::do_parse_book(code).unwrap();
   let rbook = ast::book_to_runtime(&book, run::call_native());
 
   let mut net = run::Net::new(size);
-  net.boot(name_to_val("main"));
+  net.boot(rbook.id("main").unwrap());
   (rbook, net)
 }
 
@@ -30,7 +30,7 @@ fn load_from_lang<P: AsRef<Path>>(file: P) -> (run::Book, run::Net) {
   let book = ast::book_to_runtime(&book, run::call_native());
 
   let mut net = run::Net::new(size);
-  net.boot(name_to_val("main"));
+  net.boot(book.id("main").unwrap());
   (book, net)
 }
 
@@ -159,8 +159,8 @@ fn interact_benchmark(c: &mut Criterion) {
 
   for (name, redex) in cases {
     let mut net = run::Net::new(10);
-    let book = run::Book::new();
-    ast::net_to_runtime(&mut net, &ast::Net { root: Era, rdex: vec![redex] });
+    let mut book = run::Book::new();
+    ast::net_to_runtime(&mut net, &mut book, &ast::Net { root: Era, rdex: vec![redex] });
     let (rdx_a, rdx_b) = net.rdex[0];
     group.bench_function(name, |b| {
       b.iter_batched(
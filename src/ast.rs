@@ -7,9 +7,9 @@
 
 use crate::run;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
-use std::iter::Peekable;
-use std::str::Chars;
+use std::ops::Range;
 use std::sync::Arc;
 
 // AST
@@ -18,9 +18,9 @@ use std::sync::Arc;
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub enum Tree {
   Era,
-  Ctr { lab: u8, lft: Box<Tree>, rgt: Box<Tree> },
+  Ctr { lab: run::Lab, lft: Box<Tree>, rgt: Box<Tree> },
   Var { nam: String },
-  Ref { nam: run::Val },
+  Ref { nam: String },
   Num { val: run::Val },
   Op2 { lft: Box<Tree>, rgt: Box<Tree> },
   Mat { sel: Box<Tree>, ret: Box<Tree> },
@@ -36,200 +36,409 @@ pub struct Net {
 
 pub type Book = BTreeMap<String, Net>;
 
+// The defs of a file in order, as parsed, before a def defined twice is overwritten.
+pub type Defs = Vec<(String, Net)>;
+
+// An '@include "path"' on a file, to add the defs of another file to its book. With 'as <prefix>',
+// those defs, and the refs to them, are renamed to '<prefix>.<name>'.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Include {
+  pub path: String, // relative to the including file
+  pub prefix: Option<String>,
+  pub span: Range<usize>,
+}
+
 // Parser
 // ------
 
-fn skip(chars: &mut Peekable<Chars>) {
-  while let Some(c) = chars.peek() {
-    if *c == '/' {
-      chars.next();
-      while let Some(c) = chars.peek() {
-        if *c == '\n' {
+// A parse error, with the span of the source it is about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+  pub span: Range<usize>, // bytes of the source the error points at
+  pub msg: String,
+  pub expected: Vec<String>, // what would have been accepted there, for syntax errors
+}
+
+// The source being parsed, and the byte offset of the next char.
+pub struct Parser<'a> {
+  pub code: &'a str,
+  pub pos: usize,
+}
+
+impl<'a> Parser<'a> {
+  pub fn new(code: &'a str) -> Self {
+    Parser { code, pos: 0 }
+  }
+
+  pub fn peek(&self) -> Option<char> {
+    return self.code[self.pos ..].chars().next();
+  }
+
+  pub fn next(&mut self) -> Option<char> {
+    let c = self.peek()?;
+    self.pos += c.len_utf8();
+    return Some(c);
+  }
+
+  // An error about the text parsed since 'ini'.
+  pub fn error(&self, ini: usize, msg: String) -> ParseError {
+    return ParseError { span: ini .. self.pos, msg, expected: vec![] };
+  }
+
+  // An error about the next char, which doesn't start any of the expected tokens.
+  pub fn expected(&self, expected: &[&str]) -> ParseError {
+    let (found, len) = match self.peek() {
+      Some(c) => (format!("'{}'", c), c.len_utf8()),
+      None => ("end of input".to_string(), 0),
+    };
+    return ParseError {
+      span: self.pos .. self.pos + len,
+      msg: format!("Expected {}, found {}", expected.join(" or "), found),
+      expected: expected.iter().map(|exp| exp.to_string()).collect(),
+    };
+  }
+}
+
+impl ParseError {
+  // The line and column the error starts at, counting from 1.
+  pub fn line_col(&self, code: &str) -> (usize, usize) {
+    let before = &code[.. self.span.start];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap().chars().count() + 1;
+    return (line, col);
+  }
+
+  // Shows the error with the line it is on, underlining its span.
+  pub fn render(&self, code: &str, file: &str) -> String {
+    let (line, col) = self.line_col(code);
+    let ini = code[.. self.span.start].rfind('\n').map_or(0, |i| i + 1);
+    let end = code[self.span.start ..].find('\n').map_or(code.len(), |i| self.span.start + i);
+    let len = code[self.span.start .. self.span.end.min(end)].chars().count().max(1);
+    let pad = " ".repeat(line.to_string().len());
+    let mut out = format!("error: {}\n", self.msg);
+    out.push_str(&format!("{}--> {}:{}:{}\n", pad, file, line, col));
+    out.push_str(&format!("{} |\n", pad));
+    out.push_str(&format!("{} | {}\n", line, &code[ini .. end]));
+    out.push_str(&format!("{} | {}{}\n", pad, " ".repeat(col - 1), "^".repeat(len)));
+    return out;
+  }
+}
+
+impl std::fmt::Display for ParseError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}", self.msg)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+fn skip(p: &mut Parser) {
+  while let Some(c) = p.peek() {
+    if c == '/' {
+      p.next();
+      while let Some(c) = p.peek() {
+        if c == '\n' {
           break;
         }
-        chars.next();
+        p.next();
       }
     } else if !c.is_ascii_whitespace() {
       break;
     } else {
-      chars.next();
+      p.next();
     }
   }
 }
 
-pub fn consume(chars: &mut Peekable<Chars>, text: &str) -> Result<(), String> {
-  skip(chars);
-  for c in text.chars() {
-    if chars.next() != Some(c) {
-      return Err(format!("Expected '{}', found {:?}", text, chars.peek()));
-    }
+pub fn consume(p: &mut Parser, text: &str) -> Result<(), ParseError> {
+  skip(p);
+  if !p.code[p.pos ..].starts_with(text) {
+    return Err(p.expected(&[&format!("'{}'", text)]));
   }
+  p.pos += text.len();
   return Ok(());
 }
 
-pub fn parse_decimal(chars: &mut Peekable<Chars>) -> Result<u32, String> {
-  let mut num: u32 = 0;
-  skip(chars);
-  if !chars.peek().map_or(false, |c| c.is_digit(10)) {
-    return Err(format!("Expected a decimal number, found {:?}", chars.peek()));
+pub fn parse_decimal(p: &mut Parser) -> Result<run::Val, ParseError> {
+  skip(p);
+  let ini = p.pos;
+  if !p.peek().map_or(false, |c| c.is_ascii_digit()) {
+    return Err(p.expected(&["a decimal number"]));
   }
-  while let Some(c) = chars.peek() {
-    if !c.is_digit(10) {
-      break;
-    }
-    num = num * 10 + c.to_digit(10).unwrap() as u32;
-    chars.next();
+  while p.peek().map_or(false, |c| c.is_ascii_digit()) {
+    p.next();
   }
-  Ok(num)
+  return p.code[ini .. p.pos].parse().map_err(|_| p.error(ini, "Number too large".to_string()));
 }
 
-pub fn parse_name(chars: &mut Peekable<Chars>) -> Result<String, String> {
+pub fn parse_name(p: &mut Parser) -> Result<String, ParseError> {
   let mut txt = String::new();
-  skip(chars);
-  if !chars.peek().map_or(false, |c| c.is_alphanumeric() || *c == '_' || *c == '.') {
-    return Err(format!("Expected a name character, found {:?}", chars.peek()))
+  skip(p);
+  if !p.peek().map_or(false, is_name_char) {
+    return Err(p.expected(&["a name"]));
   }
-  while let Some(c) = chars.peek() {
-    if !c.is_alphanumeric() && *c != '_' && *c != '.' {
-      break;
-    }
-    txt.push(*c);
-    chars.next();
+  while let Some(c) = p.peek().filter(|c| is_name_char(*c)) {
+    txt.push(c);
+    p.next();
   }
   Ok(txt)
 }
 
-pub fn parse_opx_lit(chars: &mut Peekable<Chars>) -> Result<String, String> {
+fn is_name_char(c: char) -> bool {
+  return c.is_alphanumeric() || c == '_' || c == '.';
+}
+
+pub fn parse_opx_lit(p: &mut Parser) -> Result<String, ParseError> {
   let mut opx = String::new();
-  skip(chars);
-  while let Some(c) = chars.peek() {
-    if !"+-=*/%<>|&^!?".contains(*c) {
-      break;
-    }
-    opx.push(*c);
-    chars.next();
+  skip(p);
+  while let Some(c) = p.peek().filter(|c| "+-=*/%<>|&^!?".contains(*c)) {
+    opx.push(c);
+    p.next();
   }
   Ok(opx)
 }
 
-pub fn parse_tree(chars: &mut Peekable<Chars>) -> Result<Tree, String> {
-  skip(chars);
-  match chars.peek() {
+// The symbol of each numeric operation, so that '#+' is the same as '#1'.
+pub const OPS: [(&str, run::Tag); 24] = [
+  ("+", run::ADD), ("-", run::SUB), ("*", run::MUL), ("/", run::DIV), ("%", run::MOD),
+  ("==", run::EQ), ("!=", run::NE), ("<", run::LT), (">", run::GT), ("<=", run::LTE), (">=", run::GTE),
+  ("&", run::AND), ("|", run::OR), ("^", run::XOR), ("!", run::NOT), ("<<", run::LSH), (">>", run::RSH),
+  ("/s", run::SDIV), ("%s", run::SMOD), ("<s", run::SLT), (">s", run::SGT), ("<=s", run::SLTE), (">=s", run::SGTE),
+  (">>s", run::SRSH),
+];
+
+// Parses the number after a '#': a decimal, a negative decimal (stored as two's complement), a
+// float (with a '.' or an exponent, or inf/NaN), or the symbol of an operation. Signed operations
+// end in 's'.
+pub fn parse_num(p: &mut Parser) -> Result<run::Val, ParseError> {
+  // Doesn't skip, since a '/' would start a comment.
+  let ini = p.pos;
+  let mut opx = String::new();
+  while let Some(c) = p.peek().filter(|c| "+-=*/%<>|&^!".contains(*c)) {
+    opx.push(c);
+    p.next();
+  }
+  if opx.is_empty() || opx == "-" && p.peek().map_or(false, |c| c.is_ascii_alphanumeric()) {
+    let mut txt = String::new();
+    while let Some(c) = p.peek().filter(|c| c.is_ascii_alphanumeric() || *c == '.' || "+-".contains(*c) && txt.ends_with('e')) {
+      txt.push(c);
+      p.next();
+    }
+    let neg = opx == "-";
+    if txt.is_empty() {
+      return Err(p.expected(&["a number"]));
+    }
+    if !txt.chars().all(|c| c.is_ascii_digit()) {
+      let val: run::FVal = txt.parse().map_err(|_| ParseError { expected: vec!["a number".to_string()], ..p.error(ini, format!("Expected a number, found '{}'", txt)) })?;
+      return Ok(run::from_float(if neg { -val } else { val }));
+    }
+    let val: run::Val = txt.parse().map_err(|_| p.error(ini, "Number too large".to_string()))?;
+    if !neg && val > run::NUM_MASK || neg && val > 1 << (run::NUM_BITS - 1) {
+      return Err(p.error(ini, format!("Number {}{} doesn't fit in {} bits", opx, val, run::NUM_BITS)));
+    }
+    return Ok(if neg { run::unsigned(-(val as run::IVal)) } else { val });
+  }
+  let opx = if p.peek() == Some('s') { p.next(); opx + "s" } else { opx };
+  match OPS.iter().find(|(sym, _)| *sym == opx) {
+    Some((_, op)) => Ok(*op as run::Val),
+    None => Err(p.error(ini, format!("Unknown operation '{}'", opx))),
+  }
+}
+
+pub fn parse_tree(p: &mut Parser) -> Result<Tree, ParseError> {
+  skip(p);
+  match p.peek() {
     Some('*') => {
-      chars.next();
+      p.next();
       Ok(Tree::Era)
     }
     Some('(') => {
-      chars.next();
+      p.next();
       let lab = 0;
-      let lft = Box::new(parse_tree(chars)?);
-      let rgt = Box::new(parse_tree(chars)?);
-      consume(chars, ")")?;
+      let lft = Box::new(parse_tree(p)?);
+      let rgt = Box::new(parse_tree(p)?);
+      consume(p, ")")?;
       Ok(Tree::Ctr { lab, lft, rgt })
     }
     Some('[') => {
-      chars.next();
+      p.next();
       let lab = 1;
-      let lft = Box::new(parse_tree(chars)?);
-      let rgt = Box::new(parse_tree(chars)?);
-      consume(chars, "]")?;
+      let lft = Box::new(parse_tree(p)?);
+      let rgt = Box::new(parse_tree(p)?);
+      consume(p, "]")?;
       Ok(Tree::Ctr { lab, lft, rgt })
     }
     Some('{') => {
-      chars.next();
-      let lab = parse_decimal(chars)? as u8;
-      let lft = Box::new(parse_tree(chars)?);
-      let rgt = Box::new(parse_tree(chars)?);
-      consume(chars, "}")?;
+      p.next();
+      skip(p);
+      let ini = p.pos;
+      let lab = parse_decimal(p)?;
+      let lab = run::Lab::try_from(lab).map_err(|_| p.error(ini, format!("Label {} doesn't fit in {} bits", lab, run::Lab::BITS)))?;
+      let lft = Box::new(parse_tree(p)?);
+      let rgt = Box::new(parse_tree(p)?);
+      consume(p, "}")?;
       Ok(Tree::Ctr { lab, lft, rgt })
     }
     Some('@') => {
-      chars.next();
-      skip(chars);
-      let name = parse_name(chars)?;
-      Ok(Tree::Ref { nam: name_to_val(&name) })
+      p.next();
+      skip(p);
+      let name = parse_name(p)?;
+      Ok(Tree::Ref { nam: name })
     }
     Some('#') => {
-      chars.next();
-      Ok(Tree::Num { val: parse_decimal(chars)? as run::Val })
+      p.next();
+      Ok(Tree::Num { val: parse_num(p)? })
     }
     Some('<') => {
-      chars.next();
-      let lft = Box::new(parse_tree(chars)?);
-      let rgt = Box::new(parse_tree(chars)?);
-      consume(chars, ">")?;
+      p.next();
+      let lft = Box::new(parse_tree(p)?);
+      let rgt = Box::new(parse_tree(p)?);
+      consume(p, ">")?;
       Ok(Tree::Op2 { lft, rgt })
     }
     Some('?') => {
-      chars.next();
-      let sel = Box::new(parse_tree(chars)?);
-      let ret = Box::new(parse_tree(chars)?);
+      p.next();
+      let sel = Box::new(parse_tree(p)?);
+      let ret = Box::new(parse_tree(p)?);
       Ok(Tree::Mat { sel, ret })
     }
+    Some(c) if is_name_char(c) => {
+      Ok(Tree::Var { nam: parse_name(p)? })
+    }
     _ => {
-      Ok(Tree::Var { nam: parse_name(chars)? })
-    },
+      Err(p.expected(&["a tree"]))
+    }
   }
 }
 
-pub fn parse_net(chars: &mut Peekable<Chars>) -> Result<Net, String> {
+pub fn parse_net(p: &mut Parser) -> Result<Net, ParseError> {
   let mut rdex = Vec::new();
-  let root = parse_tree(chars)?;
-  while let Some(c) = { skip(chars); chars.peek() } {
-    if *c == '&' {
-      chars.next();
-      let tree1 = parse_tree(chars)?;
-      consume(chars, "~")?;
-      let tree2 = parse_tree(chars)?;
-      rdex.push((tree1, tree2));
-    } else {
-      break;
-    }
+  let root = parse_tree(p)?;
+  while let Some('&') = { skip(p); p.peek() } {
+    p.next();
+    let tree1 = parse_tree(p)?;
+    consume(p, "~")?;
+    let tree2 = parse_tree(p)?;
+    rdex.push((tree1, tree2));
   }
   Ok(Net { root, rdex })
 }
 
-pub fn parse_book(chars: &mut Peekable<Chars>) -> Result<Book, String> {
+pub fn parse_string(p: &mut Parser) -> Result<String, ParseError> {
+  consume(p, "\"")?;
+  let ini = p.pos;
+  while let Some(c) = p.peek().filter(|c| *c != '"' && *c != '\n') {
+    p.next();
+  }
+  let txt = p.code[ini .. p.pos].to_string();
+  if p.peek() != Some('"') {
+    return Err(p.expected(&["'\"'"]));
+  }
+  p.next();
+  Ok(txt)
+}
+
+// Parses the defs and includes of a file. A def defined twice keeps its last definition.
+pub fn parse_file(p: &mut Parser) -> Result<(Book, Vec<Include>), ParseError> {
+  let (defs, includes) = parse_defs(p)?;
   let mut book = BTreeMap::new();
-  while let Some(c) = { skip(chars); chars.peek() } {
-    if *c == '@' {
-      chars.next();
-      let name = parse_name(chars)?;
-      consume(chars, "=")?;
-      let net = parse_net(chars)?;
-      book.insert(name, net);
-    } else {
-      break;
+  for (name, net) in defs {
+    book.insert(name, net);
+  }
+  Ok((book, includes))
+}
+
+// Parses the defs of a file in order, including the ones defined twice, and its includes.
+pub fn parse_defs(p: &mut Parser) -> Result<(Defs, Vec<Include>), ParseError> {
+  let mut defs = vec![];
+  let mut includes = vec![];
+  let mut after_net = false;
+  while let Some(c) = { skip(p); p.peek() } {
+    if c != '@' {
+      // After a def, it may also be a redex.
+      let expected: &[&str] = if after_net { &["'&'", "'@'", "end of input"] } else { &["'@'", "end of input"] };
+      return Err(p.expected(expected));
     }
+    let ini = p.pos;
+    p.next();
+    let name = parse_name(p)?;
+    skip(p);
+    if name == "include" && p.peek() == Some('"') {
+      let path = parse_string(p)?;
+      let mut span = ini .. p.pos;
+      let mut prefix = None;
+      skip(p);
+      if p.peek().map_or(false, is_name_char) {
+        let as_ini = p.pos;
+        if parse_name(p)? != "as" {
+          return Err(p.error(as_ini, "Expected 'as' or '@' or end of input".to_string()));
+        }
+        prefix = Some(parse_name(p)?);
+        span.end = p.pos;
+      }
+      includes.push(Include { path, prefix, span });
+      after_net = false;
+      continue;
+    }
+    consume(p, "=")?;
+    let net = parse_net(p)?;
+    defs.push((name, net));
+    after_net = true;
+  }
+  Ok((defs, includes))
+}
+
+// Parses a book, which can't have includes, since there's no file to find them from.
+pub fn parse_book(p: &mut Parser) -> Result<Book, ParseError> {
+  let (book, includes) = parse_file(p)?;
+  if let Some(include) = includes.first() {
+    return Err(ParseError { span: include.span.clone(), msg: "Includes can only be loaded from a file".to_string(), expected: vec![] });
   }
   Ok(book)
 }
 
-fn do_parse<T>(code: &str, parse_fn: impl Fn(&mut Peekable<Chars>) -> Result<T, String>) -> T {
-  match parse_fn(&mut code.chars().peekable()) {
-    Ok(result) => result,
-    Err(err) => {
-      eprintln!("{}", err);
-      std::process::exit(1);
-    }
+// Parses the whole code, rejecting anything after what was parsed.
+fn do_parse<T>(code: &str, parse_fn: impl Fn(&mut Parser) -> Result<T, ParseError>) -> Result<T, ParseError> {
+  let mut p = Parser::new(code);
+  let result = parse_fn(&mut p)?;
+  skip(&mut p);
+  if p.peek().is_some() {
+    return Err(p.expected(&["end of input"]));
   }
+  return Ok(result);
 }
 
-pub fn do_parse_tree(code: &str) -> Tree {
+pub fn do_parse_tree(code: &str) -> Result<Tree, ParseError> {
   do_parse(code, parse_tree)
 }
 
-pub fn do_parse_net(code: &str) -> Net {
+pub fn do_parse_net(code: &str) -> Result<Net, ParseError> {
   do_parse(code, parse_net)
 }
 
-pub fn do_parse_book(code: &str) -> Book {
+pub fn do_parse_book(code: &str) -> Result<Book, ParseError> {
   do_parse(code, parse_book)
 }
 
+pub fn do_parse_file(code: &str) -> Result<(Book, Vec<Include>), ParseError> {
+  do_parse(code, parse_file)
+}
+
+pub fn do_parse_defs(code: &str) -> Result<(Defs, Vec<Include>), ParseError> {
+  do_parse(code, parse_defs)
+}
+
 // Stringifier
 // -----------
 
+// Floats are shown with a '.' or an exponent, so they're parsed back as floats.
+pub fn show_num(val: run::Val) -> String {
+  if run::is_float(val) {
+    return format!("{:?}", run::to_float(val));
+  }
+  return val.to_string();
+}
+
 pub fn show_tree(tree: &Tree) -> String {
   match tree {
     Tree::Era => {
@@ -246,10 +455,10 @@ pub fn show_tree(tree: &Tree) -> String {
       nam.clone()
     }
     Tree::Ref { nam } => {
-      format!("@{}", val_to_name(*nam))
+      format!("@{}", nam)
     }
     Tree::Num { val } => {
-      format!("#{}", (*val as u32).to_string())
+      format!("#{}", show_num(*val))
     }
     Tree::Op2 { lft, rgt } => {
       format!("<{} {}>", show_tree(&*lft), show_tree(&*rgt))
@@ -277,18 +486,93 @@ pub fn show_book(book: &Book) -> String {
   return result;
 }
 
-pub fn show_runtime_tree(rt_net: &run::Net, ptr: run::Ptr) -> String {
-  show_tree(&tree_from_runtime_go(rt_net, ptr, PARENT_ROOT, &mut HashMap::new(), &mut 0))
+pub fn show_runtime_tree(rt_net: &run::Net, rt_book: &run::Book, ptr: run::Ptr) -> String {
+  show_tree(&tree_from_runtime_go(rt_net, rt_book, ptr, PARENT_ROOT, &mut HashMap::new(), &mut 0))
+}
+
+pub fn show_runtime_net(rt_net: &run::Net, rt_book: &run::Book) -> String {
+  show_net(&net_from_runtime(rt_net, rt_book))
 }
 
-pub fn show_runtime_net(rt_net: &run::Net) -> String {
-  show_net(&net_from_runtime(rt_net))
+// Shows a redex of a runtime net, with the names of the variables shared by both sides.
+pub fn show_runtime_redex(rt_net: &run::Net, rt_book: &run::Book, a: run::Ptr, b: run::Ptr) -> String {
+  let mut vars = HashMap::new();
+  let mut fresh = 0;
+  let tree_a = tree_from_runtime_go(rt_net, rt_book, a, Parent::Redex, &mut vars, &mut fresh);
+  let tree_b = tree_from_runtime_go(rt_net, rt_book, b, Parent::Redex, &mut vars, &mut fresh);
+  format!("{} ~ {}", show_tree(&tree_a), show_tree(&tree_b))
 }
 
 pub fn show_runtime_book(book: &run::Book) -> String {
   show_book(&book_from_runtime(book))
 }
 
+// Graphviz
+// --------
+
+// Draws a net in the DOT language. Binary nodes are triangles with their principal port on top and
+// their auxiliary ports at the bottom corners, with numeric ones filled. Wires between two
+// auxiliary ports are dashed, and active pairs are red, meeting at a red point.
+pub fn net_to_dot(net: &Net) -> String {
+  let mut dot = Dot { out: String::new(), vars: HashMap::new(), fresh: 0 };
+  dot.out.push_str("graph net {\n");
+  dot.out.push_str("  node [fontname=\"monospace\", margin=0];\n");
+  dot.out.push_str("  root [shape=plaintext, label=\"ROOT\"];\n");
+  dot.tree(&net.root, "root".to_string(), "");
+  for (i, (a, b)) in net.rdex.iter().enumerate() {
+    let rdex = format!("rdex{}", i);
+    dot.out.push_str(&format!("  {} [shape=point, color=red, width=0.15];\n", rdex));
+    dot.tree(a, rdex.clone(), "color=red, penwidth=2");
+    dot.tree(b, rdex, "color=red, penwidth=2");
+  }
+  // Variables that occur once are free wires.
+  let mut free: Vec<_> = dot.vars.drain().collect();
+  free.sort();
+  for (nam, port) in free {
+    dot.out.push_str(&format!("  \"free_{}\" [shape=plaintext, label=\"{}\"];\n", nam, nam));
+    dot.out.push_str(&format!("  {} -- \"free_{}\" [style=dashed];\n", port, nam));
+  }
+  dot.out.push_str("}\n");
+  return dot.out;
+}
+
+struct Dot {
+  out: String,
+  vars: HashMap<String, String>, // port of the first occurrence of each variable
+  fresh: usize,
+}
+
+impl Dot {
+  // Draws a tree, and the wire from its principal port to 'port'.
+  fn tree(&mut self, tree: &Tree, port: String, style: &str) {
+    let (shape, label, children) = match tree {
+      Tree::Var { nam } => {
+        match self.vars.remove(nam) {
+          Some(other) => self.out.push_str(&format!("  {} -- {} [style=dashed];\n", other, port)),
+          None => { self.vars.insert(nam.clone(), port); }
+        }
+        return;
+      }
+      Tree::Era => ("circle", "*".to_string(), None),
+      Tree::Ref { nam } => ("box", format!("@{}", nam), None),
+      Tree::Num { val } => ("ellipse", format!("#{}", show_num(*val)), None),
+      Tree::Ctr { lab, lft, rgt } => ("triangle", format!("CT{}", lab), Some((lft, rgt))),
+      Tree::Op2 { lft, rgt } => ("triangle, style=filled, fillcolor=lightblue", "OP2".to_string(), Some((lft, rgt))),
+      Tree::Mat { sel, ret } => ("triangle, style=filled, fillcolor=lightyellow", "MAT".to_string(), Some((sel, ret))),
+    };
+    let node = format!("n{}", self.fresh);
+    self.fresh += 1;
+    self.out.push_str(&format!("  {} [shape={}, label=\"{}\"];\n", node, shape, label));
+    if let Some((lft, rgt)) = children {
+      self.out.push_str(&format!("  {} -- {}:n [{}];\n", port, node, style));
+      self.tree(lft, format!("{}:sw", node), "");
+      self.tree(rgt, format!("{}:se", node), "");
+    } else {
+      self.out.push_str(&format!("  {} -- {} [{}];\n", port, node, style));
+    }
+  }
+}
+
 // Conversion
 // ----------
 
@@ -320,61 +604,597 @@ pub fn port_to_tag(port: run::Port) -> run::Tag {
   }
 }
 
-pub fn name_to_letters(name: &str) -> Vec<u8> {
-  let mut letters = Vec::new();
-  for c in name.chars() {
-    letters.push(match c {
-      '0'..='9' => c as u8 - '0' as u8 + 0,
-      'A'..='Z' => c as u8 - 'A' as u8 + 10,
-      'a'..='z' => c as u8 - 'a' as u8 + 36,
-      '_'       => 62,
-      '.'       => 63,
-      _         => panic!("Invalid character in name"),
-    });
+// Linker
+// ------
+
+// The book with '<prefix>.' before the names of its defs, and of the refs to them. Refs to names
+// it doesn't define are kept, so they can be defined by the book it is linked to.
+pub fn prefix_book(book: &Book, prefix: &str) -> Book {
+  let rename = |nam: &str| if book.contains_key(nam) { format!("{}.{}", prefix, nam) } else { nam.to_string() };
+  let mut prefixed = BTreeMap::new();
+  for (name, net) in book {
+    let root = prefix_tree(&net.root, &rename);
+    let rdex = net.rdex.iter().map(|(a, b)| (prefix_tree(a, &rename), prefix_tree(b, &rename))).collect();
+    prefixed.insert(rename(name), Net { root, rdex });
   }
-  return letters;
+  return prefixed;
 }
 
-pub fn letters_to_name(letters: Vec<u8>) -> String {
-  let mut name = String::new();
-  for letter in letters {
-    name.push(match letter {
-       0..= 9 => (letter - 0 + '0' as u8) as char,
-      10..=35 => (letter - 10 + 'A' as u8) as char,
-      36..=61 => (letter - 36 + 'a' as u8) as char,
-      62      => '_',
-      63      => '.',
-      _       => panic!("Invalid letter in name"),
-    });
+fn prefix_tree(tree: &Tree, rename: &impl Fn(&str) -> String) -> Tree {
+  match tree {
+    Tree::Ref { nam } => Tree::Ref { nam: rename(nam) },
+    Tree::Ctr { lab, lft, rgt } => Tree::Ctr { lab: *lab, lft: Box::new(prefix_tree(lft, rename)), rgt: Box::new(prefix_tree(rgt, rename)) },
+    Tree::Op2 { lft, rgt } => Tree::Op2 { lft: Box::new(prefix_tree(lft, rename)), rgt: Box::new(prefix_tree(rgt, rename)) },
+    Tree::Mat { sel, ret } => Tree::Mat { sel: Box::new(prefix_tree(sel, rename)), ret: Box::new(prefix_tree(ret, rename)) },
+    _ => tree.clone(),
   }
-  return name;
 }
 
-pub fn val_to_letters(num: run::Val) -> Vec<u8> {
-  let mut letters = Vec::new();
-  let mut num = num;
-  while num > 0 {
-    letters.push((num % 64) as u8);
-    num /= 64;
+// Adds the defs of 'other' to 'book'. A name defined by both must have the same net on both (as
+// when a file is included twice), otherwise nothing is added, and the conflicting names are
+// returned.
+pub fn link_book(book: &mut Book, other: Book) -> Result<(), Vec<String>> {
+  let conflicts: Vec<String> = other.iter().filter(|(name, net)| book.get(*name).map_or(false, |def| def != *net)).map(|(name, _)| name.clone()).collect();
+  if conflicts.len() > 0 {
+    return Err(conflicts);
+  }
+  book.extend(other);
+  return Ok(());
+}
+
+// An error while loading a file, or the files it includes.
+#[derive(Debug)]
+pub enum LoadError {
+  Io { file: String, err: std::io::Error },
+  Parse { file: String, code: String, err: ParseError },
+  Cycle { file: String }, // the file includes itself, maybe through others
+  Conflict { file: String, include: String, names: Vec<String> }, // defined differently by 'include'
+}
+
+impl std::fmt::Display for LoadError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      LoadError::Io { file, err } => write!(f, "{}: {}", file, err),
+      LoadError::Parse { file, code, err } => write!(f, "{}", err.render(code, file).trim_end()),
+      LoadError::Cycle { file } => write!(f, "{}: the file includes itself", file),
+      LoadError::Conflict { file, include, names } => {
+        let names: Vec<String> = names.iter().map(|name| format!("@{}", name)).collect();
+        write!(f, "{}: {} defines {} differently", file, include, names.join(", "))
+      }
+    }
+  }
+}
+
+impl std::error::Error for LoadError {}
+
+// Parses a file, and adds the defs of the files it includes, in order.
+pub fn load_book(file: &str) -> Result<Book, LoadError> {
+  load_book_go(std::path::Path::new(file), &mut vec![])
+}
+
+fn load_book_go(path: &std::path::Path, stack: &mut Vec<std::path::PathBuf>) -> Result<Book, LoadError> {
+  let file = path.display().to_string();
+  let code = std::fs::read_to_string(path).map_err(|err| LoadError::Io { file: file.clone(), err })?;
+  let full = path.canonicalize().map_err(|err| LoadError::Io { file: file.clone(), err })?;
+  if stack.contains(&full) {
+    return Err(LoadError::Cycle { file });
+  }
+  let (mut book, includes) = do_parse_file(&code).map_err(|err| LoadError::Parse { file: file.clone(), code, err })?;
+  stack.push(full);
+  for include in includes {
+    let inc_path = path.parent().unwrap_or(std::path::Path::new("")).join(&include.path);
+    let mut inc_book = load_book_go(&inc_path, stack)?;
+    if let Some(prefix) = &include.prefix {
+      inc_book = prefix_book(&inc_book, prefix);
+    }
+    if let Err(names) = link_book(&mut book, inc_book) {
+      return Err(LoadError::Conflict { file, include: include.path, names });
+    }
+  }
+  stack.pop();
+  return Ok(book);
+}
+
+// Loads a file for the checker: its own defs in order, including the ones defined twice, followed
+// by the defs of the files it includes.
+pub fn load_defs(file: &str) -> Result<Defs, LoadError> {
+  let book = load_book(file)?;
+  let code = std::fs::read_to_string(file).map_err(|err| LoadError::Io { file: file.to_string(), err })?;
+  let (mut defs, _) = do_parse_defs(&code).map_err(|err| LoadError::Parse { file: file.to_string(), code, err })?;
+  let own: BTreeSet<String> = defs.iter().map(|(name, _)| name.clone()).collect();
+  defs.extend(book.into_iter().filter(|(name, _)| !own.contains(name)));
+  return Ok(defs);
+}
+
+// Checker
+// -------
+
+// A mistake in a def, which would make it misbehave (or panic) on the runtime.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub def: String, // the def it was found on
+  pub msg: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "@{}: {}", self.def, self.msg)
+  }
+}
+
+// Checks that the defs of a book can be loaded on the runtime: that each variable occurs exactly
+// twice, and never on an active pair, that refs are defined, that numbers fit their encoding, and
+// that the runtime has a rule for each active pair. Labels always fit, since they have the runtime's type, and names are interned. Takes the defs as
+// parsed by 'parse_defs', or a book, so a def defined twice is reported too; the last one is used.
+pub fn check_book<'a>(defs: impl IntoIterator<Item = (&'a String, &'a Net)>) -> Vec<Diagnostic> {
+  let defs: Vec<_> = defs.into_iter().collect();
+  let mut defined = BTreeMap::new();
+  for (name, _) in &defs {
+    *defined.entry(name.as_str()).or_insert(0) += 1;
+  }
+  let mut reported = BTreeSet::new();
+  let mut diags = vec![];
+  for (name, net) in &defs {
+    let mut vars = BTreeMap::new();
+    let mut refs = BTreeSet::new();
+    let mut msgs = vec![];
+    let count = defined[name.as_str()];
+    if count > 1 && reported.insert(name.as_str()) {
+      msgs.push(format!("defined {} times (the last definition is used)", count));
+    }
+    check_tree(&net.root, &mut vars, &mut refs, &mut msgs);
+    for (a, b) in &net.rdex {
+      for tree in [a, b] {
+        if let Tree::Var { nam } = tree {
+          msgs.push(format!("variable '{}' is on an active pair", nam));
+        }
+        check_tree(tree, &mut vars, &mut refs, &mut msgs);
+      }
+      if let (Some((a_tag, a_txt)), Some((b_tag, b_txt))) = (pair_side(a), pair_side(b)) {
+        if !run::Kind::has_rule(run::Ptr::new(a_tag, 0), run::Ptr::new(b_tag, 0)) {
+          msgs.push(format!("{} and {} are on an active pair, which has no rule", a_txt, b_txt));
+        }
+      }
+    }
+    for (nam, count) in vars {
+      if count != 2 {
+        msgs.push(format!("variable '{}' occurs {} time{} (instead of twice)", nam, count, if count == 1 { "" } else { "s" }));
+      }
+    }
+    for nam in refs {
+      if !defined.contains_key(nam) {
+        msgs.push(format!("@{} isn't defined", nam));
+      }
+    }
+    diags.extend(msgs.into_iter().map(|msg| Diagnostic { def: name.to_string(), msg }));
+  }
+  return diags;
+}
+
+fn check_tree<'a>(tree: &'a Tree, vars: &mut BTreeMap<&'a str, usize>, refs: &mut BTreeSet<&'a str>, msgs: &mut Vec<String>) {
+  match tree {
+    Tree::Era => {}
+    Tree::Var { nam } => {
+      *vars.entry(nam).or_default() += 1;
+    }
+    Tree::Ref { nam } => {
+      refs.insert(nam);
+    }
+    Tree::Num { val } => {
+      // The number and the operation bits, which floats set, fill the value of a pointer.
+      if *val >> (run::NUM_BITS + run::OPR_BITS) != 0 {
+        msgs.push(format!("number {} doesn't fit in {} bits", val, run::NUM_BITS + run::OPR_BITS));
+      }
+    }
+    Tree::Ctr { lft, rgt, .. } | Tree::Op2 { lft, rgt } | Tree::Mat { sel: lft, ret: rgt } => {
+      check_tree(lft, vars, refs, msgs);
+      check_tree(rgt, vars, refs, msgs);
+    }
+  }
+}
+
+// The tag a side of an active pair has on the runtime, and what it is, or None for a variable.
+fn pair_side(tree: &Tree) -> Option<(run::Tag, &'static str)> {
+  match tree {
+    Tree::Era => Some((run::ERA, "an eraser")),
+    Tree::Var { .. } => None,
+    Tree::Ref { .. } => Some((run::REF, "a ref")),
+    Tree::Num { .. } => Some((run::NUM, "a number")),
+    Tree::Ctr { .. } => Some((run::CT0, "a constructor")),
+    Tree::Op2 { .. } => Some((run::OP2, "an operation")),
+    Tree::Mat { .. } => Some((run::MAT, "a match")),
+  }
+}
+
+// Call Graph
+// ----------
+
+// The defs each def refers to. Refs to undefined names are left out.
+pub type CallGraph = BTreeMap<String, BTreeSet<String>>;
+
+pub fn call_graph(book: &Book) -> CallGraph {
+  let mut graph = BTreeMap::new();
+  for (name, net) in book {
+    let mut refs = BTreeSet::new();
+    for tree in std::iter::once(&net.root).chain(net.rdex.iter().flat_map(|(a, b)| [a, b])) {
+      tree_refs(tree, &mut refs);
+    }
+    refs.retain(|nam| book.contains_key(nam));
+    graph.insert(name.clone(), refs);
+  }
+  return graph;
+}
+
+fn tree_refs(tree: &Tree, refs: &mut BTreeSet<String>) {
+  match tree {
+    Tree::Ref { nam } => {
+      refs.insert(nam.clone());
+    }
+    Tree::Ctr { lft, rgt, .. } | Tree::Op2 { lft, rgt } | Tree::Mat { sel: lft, ret: rgt } => {
+      tree_refs(lft, refs);
+      tree_refs(rgt, refs);
+    }
+    _ => {}
+  }
+}
+
+// The defs reachable from the roots, including them.
+pub fn reachable(graph: &CallGraph, roots: &[&str]) -> BTreeSet<String> {
+  let mut seen = BTreeSet::new();
+  let mut todo: Vec<&str> = roots.iter().copied().filter(|root| graph.contains_key(*root)).collect();
+  while let Some(name) = todo.pop() {
+    if seen.insert(name.to_string()) {
+      todo.extend(graph[name].iter().map(|nam| nam.as_str()));
+    }
+  }
+  return seen;
+}
+
+// The strongly connected components of the graph, that is, its groups of mutually recursive defs.
+// A group comes after the groups it refers to. Uses Tarjan's algorithm, without recursion, since
+// generated books can have long chains of defs.
+pub fn call_graph_sccs(graph: &CallGraph) -> Vec<Vec<String>> {
+  const NONE: usize = usize::MAX;
+  let names: Vec<&String> = graph.keys().collect();
+  let ids: HashMap<&str, usize> = names.iter().enumerate().map(|(i, name)| (name.as_str(), i)).collect();
+  let edges: Vec<Vec<usize>> = names.iter().map(|name| graph[*name].iter().map(|nam| ids[nam.as_str()]).collect()).collect();
+  let mut index = vec![NONE; names.len()];
+  let mut low = vec![NONE; names.len()];
+  let mut on_stack = vec![false; names.len()];
+  let mut stack = vec![];
+  let mut sccs = vec![];
+  let mut next = 0;
+  for root in 0 .. names.len() {
+    if index[root] != NONE {
+      continue;
+    }
+    // Each entry is a def being visited, and the next of its edges to follow.
+    let mut work = vec![(root, 0)];
+    while let Some((v, i)) = work.pop() {
+      if i == 0 {
+        index[v] = next;
+        low[v] = next;
+        next += 1;
+        stack.push(v);
+        on_stack[v] = true;
+      }
+      if let Some(&w) = edges[v].get(i) {
+        work.push((v, i + 1));
+        if index[w] == NONE {
+          work.push((w, 0));
+        } else if on_stack[w] {
+          low[v] = low[v].min(index[w]);
+        }
+        continue;
+      }
+      if low[v] == index[v] {
+        let mut scc = vec![];
+        loop {
+          let w = stack.pop().unwrap();
+          on_stack[w] = false;
+          scc.push(names[w].clone());
+          if w == v {
+            break;
+          }
+        }
+        scc.sort();
+        sccs.push(scc);
+      }
+      if let Some(&(u, _)) = work.last() {
+        low[u] = low[u].min(low[v]);
+      }
+    }
+  }
+  return sccs;
+}
+
+// Is a group of defs recursive? Either it has many defs, or its one def refers to itself.
+pub fn is_recursive(graph: &CallGraph, scc: &[String]) -> bool {
+  return scc.len() > 1 || graph[&scc[0]].contains(&scc[0]);
+}
+
+// The book without the defs that can't be reached from the roots.
+pub fn prune_book(book: &Book, roots: &[&str]) -> Book {
+  let live = reachable(&call_graph(book), roots);
+  return book.iter().filter(|(name, _)| live.contains(*name)).map(|(name, net)| (name.clone(), net.clone())).collect();
+}
+
+// Draws the call graph in the DOT language. Recursive groups are boxed together, and the defs that
+// can't be reached from the roots are grey.
+pub fn call_graph_to_dot(graph: &CallGraph, roots: &[&str]) -> String {
+  let live = reachable(graph, roots);
+  let node = |name: &str| {
+    let style = if live.contains(name) { "" } else { ", color=grey, fontcolor=grey" };
+    format!("\"{}\" [label=\"@{}\"{}];\n", name, name, style)
+  };
+  let mut out = String::new();
+  out.push_str("digraph calls {\n");
+  out.push_str("  node [fontname=\"monospace\", shape=box];\n");
+  for (i, scc) in call_graph_sccs(graph).iter().enumerate() {
+    if is_recursive(graph, scc) {
+      out.push_str(&format!("  subgraph cluster_{} {{\n", i));
+      out.push_str("    style=dashed;\n");
+      for name in scc {
+        out.push_str(&format!("    {}", node(name)));
+      }
+      out.push_str("  }\n");
+    } else {
+      out.push_str(&format!("  {}", node(&scc[0])));
+    }
+  }
+  for (name, refs) in graph {
+    for nam in refs {
+      out.push_str(&format!("  \"{}\" -> \"{}\";\n", name, nam));
+    }
+  }
+  out.push_str("}\n");
+  return out;
+}
+
+// Inliner
+// -------
+
+// The number of nodes of a net. Erasers, variables, refs and numbers aren't counted.
+pub fn net_size(net: &Net) -> usize {
+  return std::iter::once(&net.root).chain(net.rdex.iter().flat_map(|(a, b)| [a, b])).map(tree_size).sum();
+}
+
+fn tree_size(tree: &Tree) -> usize {
+  match tree {
+    Tree::Ctr { lft, rgt, .. } | Tree::Op2 { lft, rgt } | Tree::Mat { sel: lft, ret: rgt } => 1 + tree_size(lft) + tree_size(rgt),
+    _ => 0,
   }
-  letters.reverse();
-  return letters;
 }
 
-pub fn letters_to_val(letters: Vec<u8>) -> run::Val {
-  let mut num = 0;
-  for letter in letters {
-    num = num * 64 + letter as run::Val;
+// Inlines the defs that are cheaper to copy than to expand, saving a 'dref' each time they're
+// called, without changing what the book reduces to:
+// - A def without redexes whose root is an eraser, a number or a ref (an alias, as '@a = @b')
+//   always expands to that root, so each ref to it is replaced by the root.
+// - A non-recursive def with at most 'max_size' nodes is inlined on each active pair it makes with
+//   a node, where it would be expanded anyway: its root takes its place, and its redexes are added.
+// Other refs are kept, since they could be erased before being expanded. The roots are never
+// inlined, and the defs that were only used by inlined refs are dropped.
+pub fn inline_book(book: &Book, roots: &[&str], max_size: usize) -> Book {
+  let graph = call_graph(book);
+  let mut inlined = Book::new();
+  let mut inlinable = Book::new();
+  // A group comes after the groups it refers to, so a def is inlined into before it's inlined.
+  for scc in call_graph_sccs(&graph) {
+    for name in &scc {
+      inlined.insert(name.clone(), inline_net(&book[name], &inlinable, max_size));
+    }
+    if !is_recursive(&graph, &scc) && !roots.contains(&scc[0].as_str()) {
+      inlinable.insert(scc[0].clone(), inlined[&scc[0]].clone());
+    }
+  }
+  let used = reachable(&graph, roots);
+  let still_used = reachable(&call_graph(&inlined), roots);
+  inlined.retain(|name, _| still_used.contains(name) || !used.contains(name));
+  return inlined;
+}
+
+fn inline_net(net: &Net, defs: &Book, max_size: usize) -> Net {
+  let mut vars = BTreeSet::new();
+  for tree in std::iter::once(&net.root).chain(net.rdex.iter().flat_map(|(a, b)| [a, b])) {
+    tree_vars(tree, &mut vars);
+  }
+  let mut fresh = 0;
+  let mut rdex = vec![];
+  for (a, b) in &net.rdex {
+    let a = inline_atoms(a, defs);
+    let b = inline_atoms(b, defs);
+    match inline_call(&a, &b, defs, max_size).or_else(|| inline_call(&b, &a, defs, max_size)) {
+      Some((def, arg)) => {
+        // The same as expanding it: its redexes are pushed, then its root is linked to the node.
+        let mut names = HashMap::new();
+        for (x, y) in &def.rdex {
+          let x = rename_tree(x, &mut names, &mut vars, &mut fresh);
+          let y = rename_tree(y, &mut names, &mut vars, &mut fresh);
+          rdex.push((x, y));
+        }
+        rdex.push((rename_tree(&def.root, &mut names, &mut vars, &mut fresh), arg.clone()));
+      }
+      None => rdex.push((a, b)),
+    }
   }
-  return num;
+  return Net { root: inline_atoms(&net.root, defs), rdex };
 }
 
-pub fn name_to_val(name: &str) -> run::Val {
-  letters_to_val(name_to_letters(name))
+// The def of a ref on an active pair with a node, if it's small enough to inline, and the node.
+fn inline_call<'a>(a: &Tree, b: &'a Tree, defs: &'a Book, max_size: usize) -> Option<(&'a Net, &'a Tree)> {
+  match (a, b) {
+    (Tree::Ref { nam }, Tree::Ctr { .. } | Tree::Op2 { .. } | Tree::Mat { .. }) => defs.get(nam).filter(|def| net_size(def) <= max_size).map(|def| (def, b)),
+    _ => None,
+  }
 }
 
-pub fn val_to_name(num: run::Val) -> String {
-  letters_to_name(val_to_letters(num))
+// Replaces the refs to defs that are just an eraser, a number or a ref by them.
+fn inline_atoms(tree: &Tree, defs: &Book) -> Tree {
+  match tree {
+    Tree::Ref { nam } => match defs.get(nam) {
+      Some(Net { root: root @ (Tree::Era | Tree::Num { .. } | Tree::Ref { .. }), rdex }) if rdex.is_empty() => root.clone(),
+      _ => tree.clone(),
+    },
+    Tree::Ctr { lab, lft, rgt } => Tree::Ctr { lab: *lab, lft: Box::new(inline_atoms(lft, defs)), rgt: Box::new(inline_atoms(rgt, defs)) },
+    Tree::Op2 { lft, rgt } => Tree::Op2 { lft: Box::new(inline_atoms(lft, defs)), rgt: Box::new(inline_atoms(rgt, defs)) },
+    Tree::Mat { sel, ret } => Tree::Mat { sel: Box::new(inline_atoms(sel, defs)), ret: Box::new(inline_atoms(ret, defs)) },
+    _ => tree.clone(),
+  }
+}
+
+fn tree_vars(tree: &Tree, vars: &mut BTreeSet<String>) {
+  match tree {
+    Tree::Var { nam } => {
+      vars.insert(nam.clone());
+    }
+    Tree::Ctr { lft, rgt, .. } | Tree::Op2 { lft, rgt } | Tree::Mat { sel: lft, ret: rgt } => {
+      tree_vars(lft, vars);
+      tree_vars(rgt, vars);
+    }
+    _ => {}
+  }
+}
+
+// Gives the variables of an inlined def names that aren't used by the def it's inlined into.
+fn rename_tree(tree: &Tree, names: &mut HashMap<String, String>, vars: &mut BTreeSet<String>, fresh: &mut usize) -> Tree {
+  match tree {
+    Tree::Var { nam } => {
+      let nam = names.entry(nam.clone()).or_insert_with(|| loop {
+        let nam = num_to_str(*fresh);
+        *fresh += 1;
+        if vars.insert(nam.clone()) {
+          break nam;
+        }
+      });
+      return Tree::Var { nam: nam.clone() };
+    }
+    Tree::Ctr { lab, lft, rgt } => {
+      let lft = Box::new(rename_tree(lft, names, vars, fresh));
+      let rgt = Box::new(rename_tree(rgt, names, vars, fresh));
+      return Tree::Ctr { lab: *lab, lft, rgt };
+    }
+    Tree::Op2 { lft, rgt } => {
+      let lft = Box::new(rename_tree(lft, names, vars, fresh));
+      let rgt = Box::new(rename_tree(rgt, names, vars, fresh));
+      return Tree::Op2 { lft, rgt };
+    }
+    Tree::Mat { sel, ret } => {
+      let sel = Box::new(rename_tree(sel, names, vars, fresh));
+      let ret = Box::new(rename_tree(ret, names, vars, fresh));
+      return Tree::Mat { sel, ret };
+    }
+    _ => tree.clone(),
+  }
+}
+
+// Partial Evaluation
+// ------------------
+
+// Reduces the redexes of each def that don't depend on its callers, once, when the book is loaded,
+// instead of on every call. A def is only replaced if the reduced one isn't bigger, and if it got
+// there in at most 'max_rewrites' rewrites, since some nets never reach a normal form.
+pub fn pre_reduce_book(book: &Book, max_rewrites: usize) -> Book {
+  let mut reduced = Book::new();
+  for (name, net) in book {
+    let net = match pre_reduce_net(net, max_rewrites) {
+      Some(new) if net_size(&new) <= net_size(net) => new,
+      _ => net.clone(),
+    };
+    reduced.insert(name.clone(), net);
+  }
+  return reduced;
+}
+
+// Reduces the redexes of a net that can be reduced without its refs, on the runtime. Refs on an
+// active pair with a node are left there, so recursive defs aren't unrolled. So are numeric
+// operations whose second operand isn't known yet, since the runtime would leave a half-applied
+// node, which the AST has no syntax for, and pairs the runtime has no rule for, like a ref with a
+// number. Returns None if it takes more than 'max_rewrites', or more memory than a net has.
+pub fn pre_reduce_net(net: &Net, max_rewrites: usize) -> Option<Net> {
+  if net.rdex.is_empty() {
+    return Some(net.clone());
+  }
+  let mut rt_book = run::Book::new();
+  let mut rt_net = run::Net::new(1 << 18);
+  net_to_runtime(&mut rt_net, &mut rt_book, net);
+  let mut held = vec![];
+  loop {
+    let mut progress = false;
+    let mut rdex = std::mem::take(&mut held);
+    rdex.append(&mut rt_net.rdex);
+    for (a, b) in rdex {
+      let stuck = !run::Kind::has_rule(a, b) || run::Kind::of(a, b) == run::Kind::Dref;
+      if stuck || is_half_op(&rt_net, a, b) || is_half_op(&rt_net, b, a) {
+        held.push((a, b));
+      } else {
+        rt_net.interact(&rt_book, a, b).ok()?;
+        progress = true;
+      }
+    }
+    if rt_net.rewrites() > max_rewrites {
+      return None;
+    }
+    if !progress {
+      break;
+    }
+  }
+  rt_net.rdex = held;
+  return Some(net_from_runtime(&rt_net, &rt_book));
+}
+
+fn is_half_op(rt_net: &run::Net, a: run::Ptr, b: run::Ptr) -> bool {
+  return a.is_op2() && b.is_num() && !rt_net.heap.get(a.val(), run::P1).is_num();
+}
+
+// Deduplication
+// -------------
+
+// Merges the defs that are the same up to the names of their variables: one of each group is kept,
+// and the refs to the others point to it instead. A def is compared with its refs to itself
+// replaced by a placeholder, so copies of a recursive def are merged too. As merging defs can make
+// the defs that refer to them the same, it's repeated until nothing is merged. The roots are never
+// merged into another def, and are the one kept when in a group; otherwise it's the first name.
+pub fn dedup_book(book: &Book, roots: &[&str]) -> Book {
+  let mut book = book.clone();
+  loop {
+    let mut groups: HashMap<Net, Vec<&String>> = HashMap::new();
+    for (name, net) in &book {
+      groups.entry(canonical_net(name, net)).or_default().push(name);
+    }
+    let mut merged = HashMap::new();
+    for names in groups.values() {
+      let kept = names.iter().find(|name| roots.contains(&name.as_str())).unwrap_or(&names[0]);
+      for name in names {
+        if name != kept && !roots.contains(&name.as_str()) {
+          merged.insert(name.to_string(), kept.to_string());
+        }
+      }
+    }
+    if merged.is_empty() {
+      return book;
+    }
+    let rename = |nam: &str| merged.get(nam).cloned().unwrap_or_else(|| nam.to_string());
+    let mut deduped = Book::new();
+    for (name, net) in book.iter().filter(|(name, _)| !merged.contains_key(*name)) {
+      let root = prefix_tree(&net.root, &rename);
+      let rdex = net.rdex.iter().map(|(a, b)| (prefix_tree(a, &rename), prefix_tree(b, &rename))).collect();
+      deduped.insert(name.clone(), Net { root, rdex });
+    }
+    book = deduped;
+  }
+}
+
+// The net with its variables named in the order they occur, and its refs to itself nameless, so
+// two defs are the same up to renaming when their canonical nets are equal.
+pub fn canonical_net(name: &str, net: &Net) -> Net {
+  let mut names = HashMap::new();
+  let mut vars = BTreeSet::new();
+  let mut fresh = 0;
+  let rename = |nam: &str| if nam == name { String::new() } else { nam.to_string() };
+  let mut canonical = |tree: &Tree| prefix_tree(&rename_tree(tree, &mut names, &mut vars, &mut fresh), &rename);
+  let root = canonical(&net.root);
+  let rdex = net.rdex.iter().map(|(a, b)| (canonical(a), canonical(b))).collect();
+  return Net { root, rdex };
 }
 
 // Injection and Readback
@@ -389,18 +1209,18 @@ pub enum Parent {
 }
 const PARENT_ROOT: Parent = Parent::Node { val: run::ROOT.val(), port: tag_to_port(run::ROOT.tag()) };
 
-pub fn tree_to_runtime_go(rt_net: &mut run::Net, tree: &Tree, vars: &mut HashMap<String, Parent>, parent: Parent) -> run::Ptr {
+pub fn tree_to_runtime_go(rt_net: &mut run::Net, rt_book: &mut run::Book, tree: &Tree, vars: &mut HashMap<String, Parent>, parent: Parent) -> run::Ptr {
   match tree {
     Tree::Era => {
       run::ERAS
     }
     Tree::Ctr { lab, lft, rgt } => {
-      let val = rt_net.alloc(1);
-      let p1 = tree_to_runtime_go(rt_net, &*lft, vars, Parent::Node { val, port: run::P1 });
+      let val = rt_net.alloc(1).expect("the net doesn't fit in the heap");
+      let p1 = tree_to_runtime_go(rt_net, rt_book, &*lft, vars, Parent::Node { val, port: run::P1 });
       rt_net.heap.set(val, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*rgt, vars, Parent::Node { val, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, rt_book, &*rgt, vars, Parent::Node { val, port: run::P2 });
       rt_net.heap.set(val, run::P2, p2);
-      run::Ptr::new(*lab + run::CT0, val)
+      rt_net.heap.ctr(*lab, val)
     }
     Tree::Var { nam } => {
       if let Parent::Redex = parent {
@@ -424,41 +1244,42 @@ pub fn tree_to_runtime_go(rt_net: &mut run::Net, tree: &Tree, vars: &mut HashMap
       }
     }
     Tree::Ref { nam } => {
-      run::Ptr::new(run::REF, *nam)
+      run::Ptr::new(run::REF, rt_book.intern(nam))
     }
     Tree::Num { val } => {
       run::Ptr::new(run::NUM, *val as run::Val)
     }
     Tree::Op2 { lft, rgt } => {
-      let val = rt_net.alloc(1);
-      let p1 = tree_to_runtime_go(rt_net, &*lft, vars, Parent::Node { val, port: run::P1 });
+      let val = rt_net.alloc(1).expect("the net doesn't fit in the heap");
+      let p1 = tree_to_runtime_go(rt_net, rt_book, &*lft, vars, Parent::Node { val, port: run::P1 });
       rt_net.heap.set(val, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*rgt, vars, Parent::Node { val, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, rt_book, &*rgt, vars, Parent::Node { val, port: run::P2 });
       rt_net.heap.set(val, run::P2, p2);
       run::Ptr::new(run::OP2, val)
     }
     Tree::Mat { sel, ret } => {
-      let val = rt_net.alloc(1);
-      let p1 = tree_to_runtime_go(rt_net, &*sel, vars, Parent::Node { val, port: run::P1 });
+      let val = rt_net.alloc(1).expect("the net doesn't fit in the heap");
+      let p1 = tree_to_runtime_go(rt_net, rt_book, &*sel, vars, Parent::Node { val, port: run::P1 });
       rt_net.heap.set(val, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*ret, vars, Parent::Node { val, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, rt_book, &*ret, vars, Parent::Node { val, port: run::P2 });
       rt_net.heap.set(val, run::P2, p2);
       run::Ptr::new(run::MAT, val)
     }
   }
 }
 
-pub fn tree_to_runtime(rt_net: &mut run::Net, tree: &Tree) -> run::Ptr {
-  tree_to_runtime_go(rt_net, tree, &mut HashMap::new(), PARENT_ROOT)
+// Refs are given the id of their name on 'rt_book', interning it if it has none.
+pub fn tree_to_runtime(rt_net: &mut run::Net, rt_book: &mut run::Book, tree: &Tree) -> run::Ptr {
+  tree_to_runtime_go(rt_net, rt_book, tree, &mut HashMap::new(), PARENT_ROOT)
 }
 
-pub fn net_to_runtime(rt_net: &mut run::Net, net: &Net) {
+pub fn net_to_runtime(rt_net: &mut run::Net, rt_book: &mut run::Book, net: &Net) {
   let mut vars = HashMap::new();
-  let root = tree_to_runtime_go(rt_net, &net.root, &mut vars, PARENT_ROOT);
+  let root = tree_to_runtime_go(rt_net, rt_book, &net.root, &mut vars, PARENT_ROOT);
   rt_net.heap.set_root(root);
   for (tree1, tree2) in &net.rdex {
-    let ptr1 = tree_to_runtime_go(rt_net, tree1, &mut vars, Parent::Redex);
-    let ptr2 = tree_to_runtime_go(rt_net, tree2, &mut vars, Parent::Redex);
+    let ptr1 = tree_to_runtime_go(rt_net, rt_book, tree1, &mut vars, Parent::Redex);
+    let ptr2 = tree_to_runtime_go(rt_net, rt_book, tree2, &mut vars, Parent::Redex);
     rt_net.rdex.push((ptr1, ptr2));
   }
 }
@@ -466,10 +1287,14 @@ pub fn net_to_runtime(rt_net: &mut run::Net, net: &Net) {
 pub fn book_to_runtime(book: &Book, call_native: run::CallNative) -> run::Book {
   let mut rt_book = run::Book::new();
   rt_book.call_native = call_native;
+  // Defs get the first ids, in order, so a book always gets the same ones.
+  for name in book.keys() {
+    rt_book.intern(name);
+  }
   for (name, net) in book {
-    let id = name_to_val(name);
+    let id = rt_book.intern(name);
     let mut rt = run::Net::new(1 << 18);
-    net_to_runtime(&mut rt, net);
+    net_to_runtime(&mut rt, &mut rt_book, net);
     rt_book.def(id, rt.to_def());
   }
   rt_book
@@ -477,25 +1302,25 @@ pub fn book_to_runtime(book: &Book, call_native: run::CallNative) -> run::Book {
 
 // From runtime
 
-pub fn tree_from_runtime_go(rt_net: &run::Net, ptr: run::Ptr, parent: Parent, vars: &mut HashMap<Parent, String>, fresh: &mut usize) -> Tree {
+pub fn tree_from_runtime_go(rt_net: &run::Net, rt_book: &run::Book, ptr: run::Ptr, parent: Parent, vars: &mut HashMap<Parent, String>, fresh: &mut usize) -> Tree {
   match ptr.tag() {
     run::ERA => {
       Tree::Era
     }
     run::REF => {
-      Tree::Ref { nam: ptr.val() }
+      Tree::Ref { nam: rt_book.name(ptr.val()) }
     }
     run::NUM => {
       Tree::Num { val: ptr.val() as run::Val }
     }
     run::OP1 | run::OP2 => {
-      let lft = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.val(), run::P1), Parent::Node { val: ptr.val(), port: run::P1 }, vars, fresh);
-      let rgt = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.val(), run::P2), Parent::Node { val: ptr.val(), port: run::P2 }, vars, fresh);
+      let lft = tree_from_runtime_go(rt_net, rt_book, rt_net.heap.get(ptr.val(), run::P1), Parent::Node { val: ptr.val(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, rt_book, rt_net.heap.get(ptr.val(), run::P2), Parent::Node { val: ptr.val(), port: run::P2 }, vars, fresh);
       Tree::Op2 { lft: Box::new(lft), rgt: Box::new(rgt) }
     }
     run::MAT => {
-      let sel = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.val(), run::P1), Parent::Node { val: ptr.val(), port: run::P1 }, vars, fresh);
-      let ret = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.val(), run::P2), Parent::Node { val: ptr.val(), port: run::P2 }, vars, fresh);
+      let sel = tree_from_runtime_go(rt_net, rt_book, rt_net.heap.get(ptr.val(), run::P1), Parent::Node { val: ptr.val(), port: run::P1 }, vars, fresh);
+      let ret = tree_from_runtime_go(rt_net, rt_book, rt_net.heap.get(ptr.val(), run::P2), Parent::Node { val: ptr.val(), port: run::P2 }, vars, fresh);
       Tree::Mat { sel: Box::new(sel), ret: Box::new(ret) }
     }
     run::VR1 | run::VR2 => {
@@ -516,10 +1341,10 @@ pub fn tree_from_runtime_go(rt_net: &run::Net, ptr: run::Ptr, parent: Parent, va
     _ => {
       let p1  = rt_net.heap.get(ptr.val(), run::P1);
       let p2  = rt_net.heap.get(ptr.val(), run::P2);
-      let lft = tree_from_runtime_go(rt_net, p1, Parent::Node { val: ptr.val(), port: run::P1 }, vars, fresh);
-      let rgt = tree_from_runtime_go(rt_net, p2, Parent::Node { val: ptr.val(), port: run::P2 }, vars, fresh);
+      let lft = tree_from_runtime_go(rt_net, rt_book, p1, Parent::Node { val: ptr.val(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, rt_book, p2, Parent::Node { val: ptr.val(), port: run::P2 }, vars, fresh);
       Tree::Ctr {
-        lab: ptr.tag() - run::CT0,
+        lab: rt_net.heap.label(ptr),
         lft: Box::new(lft),
         rgt: Box::new(rgt),
       }
@@ -527,20 +1352,21 @@ pub fn tree_from_runtime_go(rt_net: &run::Net, ptr: run::Ptr, parent: Parent, va
   }
 }
 
-pub fn tree_from_runtime(rt_net: &run::Net, ptr: run::Ptr) -> Tree {
+// Refs are shown with the name of their id on 'rt_book'.
+pub fn tree_from_runtime(rt_net: &run::Net, rt_book: &run::Book, ptr: run::Ptr) -> Tree {
   let mut vars = HashMap::new();
   let mut fresh = 0;
-  tree_from_runtime_go(rt_net, ptr, PARENT_ROOT, &mut vars, &mut fresh)
+  tree_from_runtime_go(rt_net, rt_book, ptr, PARENT_ROOT, &mut vars, &mut fresh)
 }
 
-pub fn net_from_runtime(rt_net: &run::Net) -> Net {
+pub fn net_from_runtime(rt_net: &run::Net, rt_book: &run::Book) -> Net {
   let mut vars = HashMap::new();
   let mut fresh = 0;
   let mut rdex = Vec::new();
-  let root = tree_from_runtime_go(rt_net, rt_net.heap.get_root(), PARENT_ROOT, &mut vars, &mut fresh);
+  let root = tree_from_runtime_go(rt_net, rt_book, rt_net.heap.get_root(), PARENT_ROOT, &mut vars, &mut fresh);
   for &(a, b) in &rt_net.rdex {
-    let tree_a = tree_from_runtime_go(rt_net, a, Parent::Redex, &mut vars, &mut fresh);
-    let tree_b = tree_from_runtime_go(rt_net, b, Parent::Redex, &mut vars, &mut fresh);
+    let tree_a = tree_from_runtime_go(rt_net, rt_book, a, Parent::Redex, &mut vars, &mut fresh);
+    let tree_b = tree_from_runtime_go(rt_net, rt_book, b, Parent::Redex, &mut vars, &mut fresh);
     rdex.push((tree_a, tree_b));
   }
   Net { root, rdex }
@@ -551,8 +1377,8 @@ pub fn book_from_runtime(rt_book: &run::Book) -> Book {
   for id in 0 .. rt_book.defs.len() {
     let def = &rt_book.defs[id];
     if def.node.len() > 0 {
-      let name = val_to_name(id as run::Val);
-      let net = net_from_runtime(&run::Net::from_def(def.clone()));
+      let name = rt_book.name(id as run::Val);
+      let net = net_from_runtime(&run::Net::from_def(def.clone()), rt_book);
       book.insert(name, net);
     }
   }
@@ -2,7 +2,6 @@ use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::ast;
 use crate::ir::Stmt::SetHeap;
 use crate::ir::{Const, Function, Instr, Program, Prop, Stmt, TypeRepr, Constant};
 use crate::run::{self, Book, Def, Ptr, Val};
@@ -12,14 +11,29 @@ pub fn compile_book(book: &Book) -> Program {
   let mut values = vec![];
 
   for fid in 0..book.defs.len() as run::Val {
-    let name = ast::val_to_name(fid as Val);
+    let name = ident(book, fid as Val);
     if book.defs[fid as usize].node.len() > 0 {
       functions.push(compile_term(book, fid as Val));
-      values.push(Constant { name, value: fid as u32 });
+      values.push(Constant { name, value: fid });
     }
   }
 
-  Program { functions, values }
+  Program { functions, values, names: book.names.clone() }
+}
+
+/// The name of a def as an identifier. '_' is doubled, '.' becomes '_d', and other chars become
+/// '_u<hex>_', so different names never clash.
+pub fn ident(book: &Book, fid: Val) -> String {
+  let mut ident = String::new();
+  for c in book.name(fid).chars() {
+    match c {
+      '_' => ident.push_str("__"),
+      '.' => ident.push_str("_d"),
+      c if c.is_ascii_alphanumeric() => ident.push(c),
+      c => ident.push_str(&format!("_u{:x}_", c as u32)),
+    }
+  }
+  ident
 }
 
 pub fn compile_term(book: &Book, fid: Val) -> Function {
@@ -35,16 +49,16 @@ pub fn compile_term(book: &Book, fid: Val) -> Function {
   lowering.stmts.push(Stmt::Return(Instr::True));
 
   Function {
-    name: ast::val_to_name(fid),
+    name: ident(book, fid),
     body: lowering.stmts,
   }
 }
 
-fn assert_is_atom(ptr: Ptr) -> Instr {
+fn assert_is_atom(book: &Book, ptr: Ptr) -> Instr {
   if ptr.is_ref() {
     Instr::NewPtr {
       tag: Instr::from(Const::REF).into(),
-      value: Instr::from(Const::F(ast::val_to_name(ptr.val()))).into(),
+      value: Instr::from(Const::F(ident(book, ptr.val()))).into(),
     }
   } else {
     Instr::NewPtr {
@@ -76,6 +90,18 @@ fn compile_tag(tag: run::Tag) -> Const {
   }
 }
 
+// A pointer to a node allocated at 'lc', with the tag of 'ptr', and its label if it is a CT5.
+fn new_node(def: &Def, ptr: Ptr, lc: Instr) -> Instr {
+  if ptr.tag() == run::CT5 {
+    Instr::NewCtr {
+      lab: def.labs[ptr.val() as usize],
+      value: Box::new(lc),
+    }
+  } else {
+    Instr::new_ptr(compile_tag(ptr.tag()), lc)
+  }
+}
+
 // TODO: HVM-Lang must always output in this form.
 fn adjust_redex(rf: Ptr, rx: Ptr) -> (Ptr, Ptr) {
   if rf.is_skp() && !rx.is_skp() {
@@ -130,7 +156,7 @@ impl Lowering<'_> {
       let ref_name = self.fresh_name();
       self.stmts.push(Stmt::Let {
         name: ref_name.clone(),
-        value: assert_is_atom(rf),
+        value: assert_is_atom(self.book, rf),
       });
       self.burn(def, rx, ref_name.into());
     }
@@ -202,7 +228,7 @@ impl Lowering<'_> {
       self.make(def, p2, Instr::new_ptr(Const::VR2, lc.clone().into()));
       self
         .stmts
-        .push(Instr::new_ptr(compile_tag(ptr.tag()), lc.into()).link(target.clone()));
+        .push(new_node(def, ptr, lc.into()).link(target.clone()));
     } else if ptr.is_var() {
       match self.get(def, ptr) {
         None => {
@@ -213,7 +239,7 @@ impl Lowering<'_> {
         }
       }
     } else {
-      self.stmts.push(target.clone().link(assert_is_atom(ptr)));
+      self.stmts.push(target.clone().link(assert_is_atom(self.book, ptr)));
     }
   }
 
@@ -485,7 +511,7 @@ impl Lowering<'_> {
             Instr::new_ptr(Const::VR2, lc.clone().into()),
           );
           lowering.stmts.push(
-            Instr::new_ptr(compile_tag(ptr.tag()), lc.into()).link(Instr::from(target.clone())),
+            new_node(def, ptr, lc.into()).link(Instr::from(target.clone())),
           );
         }),
       }));
@@ -545,7 +571,7 @@ impl Lowering<'_> {
             Instr::new_ptr(Const::VR2, lc.clone().into()),
           );
           lowering.stmts.push(
-            Instr::new_ptr(compile_tag(ptr.tag()), lc.into()).link(Instr::from(target.clone())),
+            new_node(def, ptr, lc.into()).link(Instr::from(target.clone())),
           );
         }),
       }));
@@ -1,9 +1,11 @@
 use crate::run::*;
 
+pub const NAMES: &[&str] = &[];
+
 impl Net {
-  pub fn call_native(&mut self, book: &Book, ptr: Ptr, x: Ptr) -> bool {
+  pub fn call_native(&mut self, book: &Book, ptr: Ptr, x: Ptr) -> Result<bool, OutOfMemory> {
     match ptr.val() {
-      _ => { return false; }
+      _ => { return Ok(false); }
     }
   }
 }
\ No newline at end of file
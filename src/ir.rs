@@ -3,6 +3,8 @@
 
 use std::collections::HashMap;
 
+use crate::run::{Lab, Val};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TypeRepr {
   HvmPtr,
@@ -68,13 +70,14 @@ pub enum Prop {
 
 pub struct Constant {
   pub name: String,
-  pub value: u32,
+  pub value: Val,
 }
 
 /// Represents the entire IR.
 pub struct Program {
   pub functions: Vec<Function>,
   pub values: Vec<Constant>,
+  pub names: Vec<String>, // the name of each def id, for readback
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -119,7 +122,7 @@ pub enum Stmt {
 pub enum Instr {
   True,
   False,
-  Int(u32),
+  Int(Val),
   Const(Const),
   Prop(Prop),
 
@@ -164,6 +167,11 @@ pub enum Instr {
     tag: Box<Instr>,
     value: Box<Instr>,
   },
+  /// self.heap.ctr(lab, value)
+  NewCtr {
+    lab: Lab,
+    value: Box<Instr>,
+  },
 
   // FUNCTIONS:
   // These are the functions that are internal to the IR.
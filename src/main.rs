@@ -14,12 +14,19 @@ use quote::ToTokens;
 #[cfg(not(feature = "hvm_cli_options"))]
 fn main() {
   let args: Vec<String> = env::args().collect();
-  let book = run::Book::new();
-  let mut net = run::Net::new(1 << 28);
-  net.boot(ast::name_to_val("main"));
+  // The defs are compiled in, so the book only has their names.
+  let mut book = run::Book::new();
+  for name in hvmc::fns::NAMES {
+    book.intern(name);
+  }
+  let mut net = run::Net::new(run::DEFAULT_SIZE);
+  net.boot(book.id("main").expect("there's no @main"));
   let start_time = std::time::Instant::now();
-  net.normal(&book);
-  println!("{}", ast::show_runtime_net(&net));
+  if let Err(err) = net.normal(&book) {
+    eprintln!("{}", err);
+    std::process::exit(1);
+  }
+  println!("{}", ast::show_runtime_net(&net, &book));
   print_stats(&net, start_time);
 }
 
@@ -32,34 +39,130 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
   match action.as_str() {
     "run" => {
       if let Some(file_name) = f_name {
-        let (book, mut net) = load(file_name);
-        let start_time = std::time::Instant::now();
-        net.normal(&book);
-        println!("{}", ast::show_runtime_net(&net));
-        if args.len() >= 4 && args[3] == "-s" {
-          print_stats(&net, start_time);
+        let (book, net) = load(file_name, &args[3 ..]);
+        run_net(&book, net, &args[3 ..], None)?;
+      } else {
+        println!("Usage: hvmc run <file.hvmc> [-s] [--lazy] [--inline [<size>]] [--pre-reduce [<rewrites>]] [--dedup] [--memory-cap <nodes>] [--trace <file.jsonl>] [--profile [<n>]] [--checkpoint-every <rewrites> --checkpoint-file <file>]");
+        std::process::exit(1);
+      }
+    }
+    "resume" => {
+      if let Some(file_name) = f_name {
+        let loaded = fs::File::open(file_name).and_then(|file| {
+          let mut file = std::io::BufReader::new(file);
+          return Ok((run::Net::load(&mut file)?, run::Book::load(&mut file)?));
+        });
+        match loaded {
+          Ok((net, book)) => run_net(&book, net, &args[3 ..], Some(file_name))?,
+          Err(err) => {
+            eprintln!("{}: {}", file_name, err);
+            std::process::exit(1);
+          }
+        }
+      } else {
+        println!("Usage: hvmc resume <checkpoint> [-s] [--memory-cap <nodes>] [--trace <file.jsonl>] [--profile [<n>]] [--checkpoint-every <rewrites>]");
+        std::process::exit(1);
+      }
+    }
+    "debug" => {
+      if let Some(file_name) = f_name {
+        let (book, net) = load(file_name, &args[3 ..]);
+        debug(&book, net)?;
+      } else {
+        println!("Usage: hvmc debug <file.hvmc> [--inline [<size>]] [--pre-reduce [<rewrites>]] [--dedup]");
+        std::process::exit(1);
+      }
+    }
+    "check" => {
+      if let Some(file_name) = f_name {
+        let defs = match ast::load_defs(file_name) {
+          Ok(defs) => defs,
+          Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+          }
+        };
+        let diags = ast::check_book(defs.iter().map(|(name, net)| (name, net)));
+        for diag in &diags {
+          eprintln!("{}: {}", file_name, diag);
+        }
+        if diags.len() > 0 {
+          std::process::exit(1);
         }
       } else {
-        println!("Usage: hvmc run <file.hvmc> [-s]");
+        println!("Usage: hvmc check <file.hvmc>");
+        std::process::exit(1);
+      }
+    }
+    "prune" => {
+      if let Some(file_name) = f_name {
+        print!("{}", ast::show_book(&ast::prune_book(&parse(file_name), &["main"])));
+      } else {
+        println!("Usage: hvmc prune <file.hvmc>");
+        std::process::exit(1);
+      }
+    }
+    "callgraph" => {
+      if let Some(file_name) = f_name {
+        let graph = ast::call_graph(&parse(file_name));
+        if args[3 ..].iter().any(|opt| opt == "--dot") {
+          print!("{}", ast::call_graph_to_dot(&graph, &["main"]));
+        } else {
+          // Each group of mutually recursive defs, after the ones it refers to.
+          let live = ast::reachable(&graph, &["main"]);
+          for scc in ast::call_graph_sccs(&graph) {
+            let names: Vec<String> = scc.iter().map(|name| format!("@{}", name)).collect();
+            let recursive = if ast::is_recursive(&graph, &scc) { " (recursive)" } else { "" };
+            let unreachable = if live.contains(&scc[0]) { "" } else { " (unreachable)" };
+            println!("{}{}{}", names.join(" "), recursive, unreachable);
+          }
+        }
+      } else {
+        println!("Usage: hvmc callgraph <file.hvmc> [--dot]");
+        std::process::exit(1);
+      }
+    }
+    "dot" => {
+      if let Some(file_name) = f_name {
+        let net = if args[3 ..].iter().any(|opt| opt == "--after-reduce") {
+          let (book, mut net) = load(file_name, &args[3 ..]);
+          if let Err(err) = net.normal(&book) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+          }
+          ast::net_from_runtime(&net, &book)
+        } else {
+          let book = parse(file_name);
+          book.get("main").ok_or("there's no @main")?.clone()
+        };
+        print!("{}", ast::net_to_dot(&net));
+      } else {
+        println!("Usage: hvmc dot <file.hvmc> [--after-reduce]");
         std::process::exit(1);
       }
     }
     "compile" => {
       if let Some(file_name) = f_name {
-        let (book, _) = load(file_name);
+        let (book, _) = load_pruned(file_name, &args[3 ..]);
         compile_book_to_rust_crate(file_name, &book)?;
         compile_rust_crate_to_executable(file_name)?;
       } else {
-        println!("Usage: hvmc compile <file.hvmc>");
+        println!("Usage: hvmc compile <file.hvmc> [--inline [<size>]] [--pre-reduce [<rewrites>]] [--dedup]");
         std::process::exit(1);
       }
     }
     "gen-cuda-book" => {
       if let Some(file_name) = f_name {
-        let book = load(file_name).0;
-        println!("{}", gen_cuda_book(&book));
+        let book = load_pruned(file_name, &args[3 ..]).0;
+        match gen_cuda_book(&book) {
+          Ok(code) => println!("{}", code),
+          Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+          }
+        }
       } else {
-        println!("Usage: hvmc gen-cuda-book <file.hvmc>");
+        println!("Usage: hvmc gen-cuda-book <file.hvmc> [--inline [<size>]] [--pre-reduce [<rewrites>]] [--dedup]");
         std::process::exit(1);
       }
     }
@@ -67,15 +170,348 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       println!("Usage: hvmc <cmd> <file.hvmc> [-s]");
       println!("Commands:");
       println!("  run           - Run the given file");
+      println!("  resume        - Continue a run from the given checkpoint");
+      println!("  check         - Report mistakes in the given file without running it");
+      println!("  debug         - Reduce the given file step by step, interactively");
+      println!("  prune         - Show the given file without the defs @main doesn't use");
+      println!("  callgraph     - Show which defs are recursive, and which @main doesn't use");
+      println!("  dot           - Draw the given file's @main as a Graphviz graph");
       println!("  compile       - Compile the given file to an executable");
       println!("  gen-cuda-book - Generate a CUDA book from the given file");
       println!("Options:");
       println!("  [-s] Show stats, including rewrite count");
+      println!("  [--lazy] Only reduce the redexes the root depends on (run only)");
+      println!("  [--inline [<size>]] Inline the non-recursive defs with at most that many nodes (32 by default)");
+      println!("  [--pre-reduce [<rewrites>]] Reduce the redexes of each def that don't need its refs (65536 rewrites at most)");
+      println!("  [--dedup] Merge the defs that only differ in the names of their variables");
+      println!("  [--memory-cap <nodes>] Abort once more nodes would be in use");
+      println!("  [--trace <file.jsonl>] Write each interaction, expansion and link as a line of JSON");
+      println!("  [--profile [<n>]] Show the n defs with most expansions and rewrites (10 by default)");
+      println!("  [--checkpoint-every <rewrites>] Save the net every given number of rewrites");
+      println!("  [--checkpoint-file <file>] Where to save it, defaults to the resumed checkpoint");
+      println!("  [--after-reduce] Draw the normal form instead (dot only)");
+      println!("  [--dot] Draw the call graph as a Graphviz graph (callgraph only)");
     }
   }
   Ok(())
 }
 
+// Reduces a net to normal form and prints it, with the options of 'run'. The checkpoint file, if
+// any, defaults to the one resumed from.
+#[cfg(feature = "hvm_cli_options")]
+fn run_net(book: &run::Book, mut net: run::Net, opts: &[String], resumed: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+  let arg = |name: &str| opts.iter().position(|opt| opt == name).map(|i| opts.get(i + 1).ok_or(format!("{} expects a value", name)));
+  if let Some(cap) = arg("--memory-cap") {
+    net.cap = Some(cap?.parse()?);
+  }
+  let every = match arg("--checkpoint-every") {
+    Some(every) => Some(every?.parse::<usize>()?),
+    None => None,
+  };
+  let file = match arg("--checkpoint-file") {
+    Some(file) => Some(file?.as_str()),
+    None => resumed,
+  };
+  let trace = match arg("--trace") {
+    Some(file) => Some(Trace { out: std::io::BufWriter::new(fs::File::create(file?)?), names: run::Book { names: book.names.clone(), ..run::Book::new() } }),
+    None => None,
+  };
+  // Shows the top 10 defs, unless a number is given.
+  let top = opts.iter().position(|opt| opt == "--profile").map(|i| opts.get(i + 1).and_then(|n| n.parse().ok()).unwrap_or(10));
+  let profile = std::sync::Arc::new(std::sync::Mutex::new(run::Profile::new()));
+  net.observer = match (trace, top) {
+    (Some(trace), Some(_)) => Some(Box::new((trace, profile.clone()))),
+    (Some(trace), None) => Some(Box::new(trace)),
+    (None, Some(_)) => Some(Box::new(profile.clone())),
+    (None, None) => None,
+  };
+  let start_time = std::time::Instant::now();
+  let done = if let Some(every) = every {
+    let file = file.ok_or("--checkpoint-every needs a --checkpoint-file")?;
+    if opts.iter().any(|opt| opt == "--lazy") {
+      return Err("--lazy can't be checkpointed".into());
+    }
+    // Saves after every batch of rewrites, and when running out of memory, so it can be resumed
+    // with a larger cap.
+    loop {
+      let status = net.reduce_with_fuel(book, every);
+      if let Err(err) = save_checkpoint(file, book, &net) {
+        eprintln!("{}: {}", file, err);
+        std::process::exit(1);
+      }
+      match status {
+        Ok(run::Status::OutOfFuel) => continue,
+        Ok(run::Status::Normal) => break Ok(()),
+        Err(err) => break Err(err),
+      }
+    }
+  } else if opts.iter().any(|opt| opt == "--lazy") {
+    net.normal_lazy(book)
+  } else {
+    net.normal(book)
+  };
+  // Flushes the trace.
+  net.observer = None;
+  if let Err(err) = done {
+    eprintln!("{}", err);
+    std::process::exit(1);
+  }
+  println!("{}", ast::show_runtime_net(&net, book));
+  if opts.iter().any(|opt| opt == "-s") {
+    print_stats(&net, start_time);
+  }
+  if let Some(top) = top {
+    print_profile(book, &profile.lock().unwrap(), top);
+  }
+  return Ok(());
+}
+
+// Writes a checkpoint next to the file and renames it, so a run killed while saving keeps the
+// previous one.
+#[cfg(feature = "hvm_cli_options")]
+fn save_checkpoint(file: &str, book: &run::Book, net: &run::Net) -> std::io::Result<()> {
+  use std::io::Write;
+  let tmp = format!("{}.tmp", file);
+  let mut out = std::io::BufWriter::new(fs::File::create(&tmp)?);
+  net.save(&mut out)?;
+  book.save(&mut out)?;
+  out.flush()?;
+  drop(out);
+  return fs::rename(tmp, file);
+}
+
+#[cfg(feature = "hvm_cli_options")]
+fn print_profile(book: &run::Book, profile: &run::Profile, top: usize) {
+  println!("PROFILE: top {} of {} defs", top.min(profile.defs.len()), profile.defs.len());
+  println!("{:<24} {:>12} {:>12} {:>12}", "DEF", "CALLS", "NODES", "RWTS");
+  for (id, cost) in profile.top(top) {
+    println!("{:<24} {:>12} {:>12} {:>12}", format!("@{}", book.name(id)), cost.calls, cost.nodes, cost.rewrites);
+  }
+}
+
+#[cfg(feature = "hvm_cli_options")]
+const DEBUG_HELP: &str = "\
+Commands:
+  s, step [n]     - Reduce the oldest redex (or n of them), expanding the root if there are none
+  r, round        - Reduce the redexes pending now, like a round of 'reduce'
+  c, continue     - Reduce until a breakpoint is hit, or the net is in normal form
+  p, print        - Show the net
+  x, rdex         - Show the pending redexes, oldest first
+  b, break [@def] - Stop when a def is expanded, or list the breakpoints
+  d, delete @def  - Remove a breakpoint
+  h, help         - Show this
+  q, quit         - Quit";
+
+// The defs to stop at, and the last one expanded, if any.
+#[cfg(feature = "hvm_cli_options")]
+struct Breakpoints {
+  ids: std::collections::BTreeSet<run::Val>,
+  hit: Option<run::Val>,
+}
+
+#[cfg(feature = "hvm_cli_options")]
+impl run::Observer for Breakpoints {
+  fn call(&mut self, id: run::Val, locs: &[run::Val]) {
+    if self.ids.contains(&id) {
+      self.hit = Some(id);
+    }
+  }
+}
+
+// Reduces a net interactively.
+#[cfg(feature = "hvm_cli_options")]
+fn debug(book: &run::Book, mut net: run::Net) -> Result<(), Box<dyn std::error::Error>> {
+  use std::io::Write;
+  let breaks = std::sync::Arc::new(std::sync::Mutex::new(Breakpoints { ids: std::collections::BTreeSet::new(), hit: None }));
+  net.observer = Some(Box::new(breaks.clone()));
+  println!("{}", DEBUG_HELP);
+  let mut line = String::new();
+  loop {
+    print!("(hvmc) ");
+    std::io::stdout().flush()?;
+    line.clear();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+      return Ok(());
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+      [] => {}
+      ["s" | "step"] => {
+        debug_step(book, &mut net, &breaks, true);
+      }
+      ["s" | "step", n] => match n.parse::<usize>() {
+        Ok(n) => {
+          for _ in 0 .. n {
+            if !debug_step(book, &mut net, &breaks, true) {
+              break;
+            }
+          }
+        }
+        Err(_) => println!("'{}' isn't a number of steps", n),
+      },
+      ["r" | "round"] => {
+        let len = net.rdex.len();
+        if len == 0 {
+          debug_step(book, &mut net, &breaks, true);
+        }
+        let mut done = 0;
+        while done < len && debug_step(book, &mut net, &breaks, false) {
+          done += 1;
+        }
+        println!("reduced {} of {} redexes, {} pending", done, len, net.rdex.len());
+      }
+      ["c" | "continue"] => {
+        let start = net.rewrites();
+        while debug_step(book, &mut net, &breaks, false) {}
+        println!("{} rewrites", net.rewrites() - start);
+      }
+      ["p" | "print"] => {
+        println!("{}", ast::show_runtime_net(&net, book));
+      }
+      ["x" | "rdex"] => {
+        for (i, &(a, b)) in net.rdex.iter().enumerate() {
+          println!("{:>6}: {}", i, ast::show_runtime_redex(&net, book, a, b));
+        }
+        println!("{} pending", net.rdex.len());
+      }
+      ["b" | "break"] => {
+        for id in &breaks.lock().unwrap().ids {
+          println!("@{}", book.name(*id));
+        }
+      }
+      ["b" | "break", name] => {
+        let name = name.trim_start_matches('@');
+        match book.id(name) {
+          Some(id) => {
+            breaks.lock().unwrap().ids.insert(id);
+            println!("breakpoint on @{}", name);
+          }
+          None => println!("@{} isn't defined", name),
+        }
+      }
+      ["d" | "delete", name] => {
+        let id = book.id(name.trim_start_matches('@'));
+        if !id.map_or(false, |id| breaks.lock().unwrap().ids.remove(&id)) {
+          println!("no breakpoint on {}", name);
+        }
+      }
+      ["q" | "quit"] => {
+        return Ok(());
+      }
+      _ => {
+        println!("{}", DEBUG_HELP);
+      }
+    }
+  }
+}
+
+// Reduces a redex for the debugger, showing it if 'show' is set. Returns false, after saying why,
+// if the session should stop: the net is in normal form, a breakpoint was hit, or the heap is full.
+#[cfg(feature = "hvm_cli_options")]
+fn debug_step(book: &run::Book, net: &mut run::Net, breaks: &std::sync::Mutex<Breakpoints>, show: bool) -> bool {
+  if show {
+    match net.rdex.first() {
+      Some(&(a, b)) => println!("{}", ast::show_runtime_redex(net, book, a, b)),
+      None => println!("expanding the root, and reducing its oldest redex"),
+    }
+  }
+  match net.step(book) {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      println!("normal form");
+      return false;
+    }
+    Err(err) => {
+      println!("{}", err);
+      return false;
+    }
+  }
+  if let Some(id) = breaks.lock().unwrap().hit.take() {
+    println!("breakpoint: @{} was expanded", book.name(id));
+    return false;
+  }
+  return true;
+}
+
+// Writes each step of a reduction as a line of JSON.
+#[cfg(feature = "hvm_cli_options")]
+struct Trace {
+  out: std::io::BufWriter<fs::File>,
+  names: run::Book, // with just the names of the book being run, to show refs
+}
+
+#[cfg(feature = "hvm_cli_options")]
+impl Trace {
+  fn write(&mut self, line: String) {
+    use std::io::Write;
+    if let Err(err) = writeln!(self.out, "{}", line) {
+      eprintln!("trace: {}", err);
+      std::process::exit(1);
+    }
+  }
+}
+
+#[cfg(feature = "hvm_cli_options")]
+impl run::Observer for Trace {
+  fn interact(&mut self, kind: run::Kind, a: run::Ptr, b: run::Ptr) {
+    self.write(format!("{{\"event\":\"interact\",\"kind\":\"{}\",\"a\":{},\"b\":{}}}", kind.name(), show_ptr(&self.names, a), show_ptr(&self.names, b)));
+  }
+
+  fn call(&mut self, id: run::Val, locs: &[run::Val]) {
+    let locs: Vec<String> = locs.iter().map(|loc| loc.to_string()).collect();
+    self.write(format!("{{\"event\":\"call\",\"ref\":{},\"locs\":[{}]}}", show_str(&self.names.name(id)), locs.join(",")));
+  }
+
+  fn link(&mut self, a: run::Ptr, b: run::Ptr) {
+    self.write(format!("{{\"event\":\"link\",\"a\":{},\"b\":{}}}", show_ptr(&self.names, a), show_ptr(&self.names, b)));
+  }
+}
+
+#[cfg(feature = "hvm_cli_options")]
+impl Drop for Trace {
+  fn drop(&mut self) {
+    use std::io::Write;
+    if let Err(err) = self.out.flush() {
+      eprintln!("trace: {}", err);
+    }
+  }
+}
+
+// Shows a pointer as JSON: its tag, and its node index, REF name or number.
+#[cfg(feature = "hvm_cli_options")]
+fn show_ptr(names: &run::Book, ptr: run::Ptr) -> String {
+  let tag = match ptr.tag() {
+    run::VR1 => "VR1".to_string(),
+    run::VR2 => "VR2".to_string(),
+    run::RD1 => "RD1".to_string(),
+    run::RD2 => "RD2".to_string(),
+    run::REF => return format!("{{\"tag\":\"REF\",\"ref\":{}}}", show_str(&names.name(ptr.val()))),
+    run::ERA => return "{\"tag\":\"ERA\"}".to_string(),
+    run::NUM => return format!("{{\"tag\":\"NUM\",\"num\":{}}}", ptr.val()),
+    run::OP2 => "OP2".to_string(),
+    run::OP1 => "OP1".to_string(),
+    run::MAT => "MAT".to_string(),
+    tag => format!("CT{}", tag - run::CT0),
+  };
+  return format!("{{\"tag\":\"{}\",\"loc\":{}}}", tag, ptr.val());
+}
+
+// Shows a string as JSON, escaping quotes, backslashes and control characters.
+#[cfg(feature = "hvm_cli_options")]
+fn show_str(txt: &str) -> String {
+  let mut out = String::from("\"");
+  for c in txt.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+  return out;
+}
+
 fn print_stats(net: &run::Net, start_time: std::time::Instant) {
   println!("RWTS   : {}", net.anni + net.comm + net.eras + net.dref + net.oper);
   println!("- ANNI : {}", net.anni);
@@ -87,12 +523,54 @@ fn print_stats(net: &run::Net, start_time: std::time::Instant) {
   println!("RPS    : {:.3} m", (net.rewrites() as f64) / (start_time.elapsed().as_millis() as f64) / 1000.0);
 }
 
+// Parses a file and its includes, showing where the error is if it fails.
+fn parse(file: &str) -> ast::Book {
+  match ast::load_book(file) {
+    Ok(book) => book,
+    Err(err) => {
+      eprintln!("{}", err);
+      std::process::exit(1);
+    }
+  }
+}
+
 // Load file and generate net
-fn load(file: &str) -> (run::Book, run::Net) {
-  let file = fs::read_to_string(file).unwrap();
-  let book = ast::book_to_runtime(&ast::do_parse_book(&file), run::call_native());
-  let mut net = run::Net::new(1 << 28);
-  net.boot(ast::name_to_val("main"));
+fn load(file: &str, opts: &[String]) -> (run::Book, run::Net) {
+  return load_book(file, &optimize(parse(file), opts));
+}
+
+// Load file without the defs @main doesn't use, for the commands that output the whole book.
+fn load_pruned(file: &str, opts: &[String]) -> (run::Book, run::Net) {
+  return load_book(file, &ast::prune_book(&optimize(parse(file), opts), &["main"]));
+}
+
+// With '--inline [<size>]', inlines the defs with at most that many nodes into their callers. Then,
+// with '--pre-reduce [<rewrites>]', reduces what it can of each def in at most that many rewrites.
+// Last, with '--dedup', merges the defs that are the same.
+fn optimize(mut book: ast::Book, opts: &[String]) -> ast::Book {
+  let arg = |name: &str, default: usize| opts.iter().position(|opt| opt == name).map(|i| opts.get(i + 1).and_then(|n| n.parse().ok()).unwrap_or(default));
+  if let Some(size) = arg("--inline", 32) {
+    book = ast::inline_book(&book, &["main"], size);
+  }
+  if let Some(max_rewrites) = arg("--pre-reduce", 1 << 16) {
+    book = ast::pre_reduce_book(&book, max_rewrites);
+  }
+  if opts.iter().any(|opt| opt == "--dedup") {
+    book = ast::dedup_book(&book, &["main"]);
+  }
+  return book;
+}
+
+fn load_book(file: &str, book: &ast::Book) -> (run::Book, run::Net) {
+  let book = ast::book_to_runtime(book, run::call_native());
+  let mut net = run::Net::new(run::DEFAULT_SIZE);
+  match book.id("main") {
+    Some(main) => net.boot(main),
+    None => {
+      eprintln!("{}: there's no @main", file);
+      std::process::exit(1);
+    }
+  }
   return (book, net);
 }
 
@@ -105,7 +583,8 @@ pub fn compile_book_to_rust_crate(f_name: &str, book: &run::Book) -> Result<(),
   }
   let cargo_toml = include_str!("../Cargo.toml");
   let cargo_toml = cargo_toml.split("##--COMPILER-CUTOFF--##").next().unwrap();
-  let cargo_toml = cargo_toml.replace("\"hvm_cli_options\"", "");
+  // The compiled crate keeps the pointer width of this build.
+  let cargo_toml = cargo_toml.replace("\"hvm_cli_options\"", if cfg!(feature = "ptr64") { "\"ptr64\"" } else { "" });
   fs::create_dir_all(&format!("{}/src", outdir))?;
   fs::write(".hvm/Cargo.toml", cargo_toml)?;
   fs::write(".hvm/src/ast.rs", include_str!("../src/ast.rs"))?;
@@ -131,12 +610,27 @@ pub fn compile_rust_crate_to_executable(f_name: &str) -> Result<(), std::io::Err
 }
 
 // TODO: move to hvm-cuda repo
-pub fn gen_cuda_book(book: &run::Book) -> String {
+pub fn gen_cuda_book(book: &run::Book) -> Result<String, String> {
   use std::collections::BTreeMap;
 
+  if cfg!(feature = "ptr64") {
+    return Err("The CUDA runtime only supports 32-bit pointers".to_string());
+  }
+
   // Sort the book.defs by key
   let mut defs = BTreeMap::new();
   for i in 0 .. book.defs.len() {
+    // The CUDA runtime only knows the operations up to RSH, so it would misread the others, and
+    // floats, which set all the operation bits.
+    let ptrs = book.defs[i].node.iter().chain(&book.defs[i].rdex).flat_map(|(a, b)| [a, b]);
+    if ptrs.filter(|ptr| ptr.is_num()).any(|ptr| ptr.val() >> run::NUM_BITS > run::RSH as run::Val) {
+      return Err(format!("@{} has a float or an operation after '>>', which the CUDA runtime doesn't support", book.name(i as run::Val)));
+    }
+    // The CUDA runtime keeps labels on the tag, where CT5 is label 5, so a CT5 node with label 5 is
+    // emitted as it is, but bigger labels have no tag.
+    if book.defs[i].labs.iter().any(|lab| *lab > run::LABS) {
+      return Err(format!("@{} has a label over {}, which the CUDA runtime doesn't support", book.name(i as run::Val), run::LABS));
+    }
     if book.defs[i].node.len() > 0 {
       defs.insert(i as run::Val, book.defs[i].clone());
     }
@@ -147,7 +641,7 @@ pub fn gen_cuda_book(book: &run::Book) -> String {
 
   // Generate function ids
   for (i, id) in defs.keys().enumerate() {
-    code.push_str(&format!("const u32 F_{} = 0x{:x};\n", crate::ast::val_to_name(*id), id));
+    code.push_str(&format!("const u32 F_{} = 0x{:x};\n", hvmc::codegen::ident(book, *id), id));
   }
   code.push_str("\n");
 
@@ -159,7 +653,7 @@ pub fn gen_cuda_book(book: &run::Book) -> String {
     let node_len = net.node.len();
     let rdex_len = net.rdex.len();
 
-    code.push_str(&format!("  // @{}\n", crate::ast::val_to_name(*id)));
+    code.push_str(&format!("  // @{}\n", book.name(*id)));
 
     // Collect all pointers from root, nodes and rdex into a single buffer
     code.push_str(&format!("  // .nlen\n"));
@@ -200,11 +694,11 @@ pub fn gen_cuda_book(book: &run::Book) -> String {
 
   let mut index = 0;
   for (i, id) in defs.keys().enumerate() {
-    code.push_str(&format!("  0x{:08X}, 0x{:08X}, // @{}\n", id, index, crate::ast::val_to_name(*id)));
+    code.push_str(&format!("  0x{:08X}, 0x{:08X}, // @{}\n", id, index, book.name(*id)));
     index += 2 + 2 * defs[id].node.len() as u32 + 2 * defs[id].rdex.len() as u32;
   }
 
   code.push_str("};");
 
-  return code;
+  return Ok(code);
 }
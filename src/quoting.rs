@@ -9,6 +9,7 @@ impl ToTokens for Program {
   fn to_tokens(&self, tokens: &mut TokenStream) {
     let constants = &self.values;
     let functions = &self.functions;
+    let names = &self.names;
     let cases = functions.iter().map(|function| {
       let name = format_ident!("F_{}", function.name);
 
@@ -20,13 +21,15 @@ impl ToTokens for Program {
 
       #( #constants )*
 
+      pub const NAMES: &[&str] = &[#( #names ),*];
+
       impl Net {
         #( #functions )*
 
-        pub fn call_native(&mut self, book: &Book, ptr: Ptr, argument: Ptr) -> bool {
+        pub fn call_native(&mut self, book: &Book, ptr: Ptr, argument: Ptr) -> Result<bool, OutOfMemory> {
           match ptr.val() {
             #( #cases )*
-            _ => { return false; }
+            _ => { return Ok(false); }
           }
         }
       }
@@ -40,7 +43,7 @@ impl ToTokens for Function {
     let body = &self.body;
 
     tokens.append_all(quote! {
-      pub fn #name(&mut self, book: &Book, ptr: Ptr, argument: Ptr) -> bool {
+      pub fn #name(&mut self, book: &Book, ptr: Ptr, argument: Ptr) -> Result<bool, OutOfMemory> {
         #( #body )*
       }
     })
@@ -83,7 +86,7 @@ impl ToTokens for Stmt {
       },
       Stmt::Instr(instr) => quote! { #instr; },
       Stmt::Free(value) => quote! { self.free(#value); },
-      Stmt::Return(value) => quote! { return #value; },
+      Stmt::Return(value) => quote! { return Ok(#value); },
       Stmt::SetHeap { idx, port, value } => quote! { self.heap.set(#idx, #port, #value); },
       Stmt::Link { lhs, rhs } => quote! { self.link(#lhs, #rhs); },
     })
@@ -145,8 +148,9 @@ impl ToTokens for Instr {
       Instr::IsNum { ins } => quote! { #ins.is_num() },
       Instr::IsSkp { ins } => quote! { #ins.is_skp() },
       Instr::NewPtr { tag, value } => quote! { Ptr::new(#tag, #value) },
+      Instr::NewCtr { lab, value } => quote! { self.heap.ctr(#lab, #value) },
       Instr::Op { lhs, rhs } => quote! { self.op(#lhs, #rhs) },
-      Instr::Alloc { size } => quote! { self.alloc(#size) },
+      Instr::Alloc { size } => quote! { self.alloc(#size)? },
       Instr::GetHeap { idx, port } => quote! { self.heap.get(#idx, #port) },
       Instr::Bin { op, lhs, rhs } => {
         match op.as_str() {
@@ -179,7 +183,7 @@ impl ToTokens for Constant {
     let value = TokenStream::from_str(&format!("0x{:06x}", self.value)).unwrap();
 
     tokens.append_all(quote! {
-      pub const #name: u32 = #value;
+      pub const #name: Val = #value;
     })
   }
 }
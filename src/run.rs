@@ -6,13 +6,41 @@
 // that ERAs, NUMs and REFs don't use any additional space. REFs lazily expand to closed nets when
 // they interact with nodes, and are cleared when they interact with ERAs, allowing for constant
 // space evaluation of recursive functions on Scott encoded datatypes.
-
-use std::sync::Arc;
-use std::sync::atomic::{AtomicU32, Ordering};
-
+//
+// The heap can also be shared by many threads, each one reducing its own redexes. In that mode,
+// wires are linked with the lock-free algorithm described on paper/draft.tex: a thread takes the
+// ports it owns, leaves redirections (RD1/RD2) behind, and principal ports travel through them
+// until they reach an aux port or meet another principal port, forming a new redex.
+//
+// In lazy mode, redexes are held until the root depends on them. Each node also remembers which
+// aux port its main port is linked to, so that, from an aux port, we can walk up to the redex
+// blocking it. That allows evaluating recursive terms that would expand forever when eager.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+
+// Pointers are 32-bit by default. The "ptr64" feature makes them 64-bit, for bigger heaps and
+// wider numbers.
 pub type Tag  = u8;
+pub type Lab  = u16;
+#[cfg(not(feature = "ptr64"))]
 pub type Val  = u32;
-pub type AVal = AtomicU32;
+#[cfg(not(feature = "ptr64"))]
+pub type AVal = std::sync::atomic::AtomicU32;
+#[cfg(feature = "ptr64")]
+pub type Val  = u64;
+#[cfg(feature = "ptr64")]
+pub type AVal = std::sync::atomic::AtomicU64;
+#[cfg(not(feature = "ptr64"))]
+pub type IVal = i32;
+#[cfg(feature = "ptr64")]
+pub type IVal = i64;
+#[cfg(not(feature = "ptr64"))]
+pub type FVal = f32;
+#[cfg(feature = "ptr64")]
+pub type FVal = f64;
 
 // Core terms.
 pub const VR1: Tag = 0x0; // Variable to aux port 1
@@ -30,7 +58,11 @@ pub const CT1: Tag = 0xB; // Main port of con node, label 1
 pub const CT2: Tag = 0xC; // Main port of con node, label 2
 pub const CT3: Tag = 0xD; // Main port of con node, label 3
 pub const CT4: Tag = 0xE; // Main port of con node, label 4
-pub const CT5: Tag = 0xF; // Main port of con node, label 5
+pub const CT5: Tag = 0xF; // Main port of con node, label on the heap
+
+// Labels below LABS are kept on the tag, as CT0..CT4. Bigger ones use CT5, and are stored on the
+// node, so a net can have up to 65536 labels.
+pub const LABS: Lab = (CT5 - CT0) as Lab;
 
 // Numeric operations.
 pub const USE: Tag = 0x0; // set-next-op
@@ -49,10 +81,52 @@ pub const XOR: Tag = 0xC; // logical-xor
 pub const NOT: Tag = 0xD; // logical-not
 pub const LSH: Tag = 0xE; // left-shift
 pub const RSH: Tag = 0xF; // right-shift
+pub const LTE: Tag = 0x10; // less-than-or-equal
+pub const GTE: Tag = 0x11; // greater-than-or-equal
+pub const SDIV: Tag = 0x12; // signed division
+pub const SMOD: Tag = 0x13; // signed modulus
+pub const SLT: Tag = 0x14; // signed less-than
+pub const SGT: Tag = 0x15; // signed greater-than
+pub const SLTE: Tag = 0x16; // signed less-than-or-equal
+pub const SGTE: Tag = 0x17; // signed greater-than-or-equal
+pub const SRSH: Tag = 0x18; // arithmetic right-shift
+pub const I2F: Tag = 0x19; // signed integer to float
+pub const F2I: Tag = 0x1A; // float to signed integer
 
 pub const ERAS: Ptr   = Ptr::new(ERA, 0);
 pub const ROOT: Ptr   = Ptr::new(VR2, 0);
 pub const NULL: Ptr   = Ptr(0x0000_0000);
+pub const GONE: Ptr   = Ptr(Val::MAX - 1); // a port whose redex was already created
+pub const LOCK: Ptr   = Ptr(Val::MAX); // a port taken by another thread
+
+// A NUM holds a 5-bit operation (for OP1 nodes) and a number, which is 23-bit with 32-bit pointers
+// and 55-bit with 64-bit pointers. The operations after RSH don't fit in 4 bits, so the number
+// gives up a bit for them. Arithmetic wraps at that width. Signed operations read numbers as two's
+// complement.
+pub const OPR_BITS: u32 = 5;
+pub const NUM_BITS: u32 = Val::BITS - 4 - OPR_BITS;
+pub const NUM_MASK: Val = (1 << NUM_BITS) - 1;
+pub const OPR_MASK: Val = (1 << OPR_BITS) - 1;
+
+// A NUM whose operation bits are all set is a float: an f32 (or an f64, with 64-bit pointers) with
+// the lowest 9 bits of its mantissa rounded off. Operations are on floats when their right operand
+// is one.
+pub const FLOAT: Val = OPR_MASK;
+
+// Def ids are the low 24 bits of a REF.
+pub const DEF_MASK: Val = 0xFFFFFF;
+
+// The largest heap a pointer can address. With 64-bit pointers, it is limited by the segments.
+#[cfg(not(feature = "ptr64"))]
+pub const MAX_SIZE: usize = 1 << 28;
+#[cfg(feature = "ptr64")]
+pub const MAX_SIZE: usize = SEG0 << 31;
+
+// The heap size used by the CLI.
+#[cfg(not(feature = "ptr64"))]
+pub const DEFAULT_SIZE: usize = 1 << 28;
+#[cfg(feature = "ptr64")]
+pub const DEFAULT_SIZE: usize = 1 << 32;
 
 // An auxiliary port.
 pub type Port = Val;
@@ -66,21 +140,147 @@ pub struct Ptr(pub Val);
 // An atomic tagged pointer.
 pub struct APtr(pub AVal);
 
+// The nodes of a net. They're split in segments, which are only allocated when the net grows into
+// them, so that a big heap costs nothing until it is used. Each segment doubles the heap, and is
+// never moved, so indices stay valid as it grows.
 pub struct Heap {
-  pub data: Vec<(APtr, APtr)>,
+  pub segs: Arc<Vec<Vec<(APtr, APtr)>>>, // allocated segments
+  pub base: [*const (APtr, APtr); 32], // where index 0 would be, for each segment
+  pub labs: Arc<Vec<Vec<AtomicU16>>>, // label of each node, for CT5 nodes
+  pub lbase: [*const AtomicU16; 32], // where index 0 would be, for each label segment
+  pub size: usize, // max nodes
+  pub len: usize, // nodes in allocated segments
+  pub head: Vec<APtr>, // aux port linked to each main port, in lazy mode
 }
 
+// The length of the first segment. Must be a power of two.
+pub const SEG0: usize = 1 << 12;
+
 // A interaction combinator net.
 pub struct Net {
   pub rdex: Vec<(Ptr,Ptr)>, // redexes
   pub heap: Heap, // nodes
   pub locs: Vec<Val>,
   pub next: usize,
+  pub area: (usize, usize), // alloc area, when sharing the heap
+  pub tids: usize, // threads sharing the heap
+  pub cap: Option<usize>, // max nodes to use
+  pub hold: HashMap<Val, (Ptr, Ptr)>, // redexes of each node, in lazy mode
   pub anni: usize, // anni rewrites
   pub comm: usize, // comm rewrites
   pub eras: usize, // eras rewrites
   pub dref: usize, // dref rewrites
   pub oper: usize, // oper rewrites
+  pub observer: Option<Box<dyn Observer + Send>>, // notified of each step, for tracing
+}
+
+// The kind of an interaction, matching the rewrite counters. Links are redexes with a variable,
+// which are only made when sharing the heap.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+  Anni,
+  Comm,
+  Eras,
+  Dref,
+  Oper,
+  Link,
+}
+
+// Is notified of the steps of a reduction. Forked nets have no observer, so only the sequential
+// and lazy modes are observed.
+pub trait Observer {
+  // A redex is about to be reduced.
+  fn interact(&mut self, kind: Kind, a: Ptr, b: Ptr) {}
+  // A REF was expanded, and its nodes were loaded at these indices. Native functions load none.
+  fn call(&mut self, id: Val, locs: &[Val]) {}
+  // Two pointers were linked.
+  fn link(&mut self, a: Ptr, b: Ptr) {}
+  // A node was allocated.
+  fn alloc(&mut self, loc: Val) {}
+}
+
+// Observes with both observers.
+impl<A: Observer, B: Observer> Observer for (A, B) {
+  fn interact(&mut self, kind: Kind, a: Ptr, b: Ptr) {
+    self.0.interact(kind, a, b);
+    self.1.interact(kind, a, b);
+  }
+
+  fn call(&mut self, id: Val, locs: &[Val]) {
+    self.0.call(id, locs);
+    self.1.call(id, locs);
+  }
+
+  fn link(&mut self, a: Ptr, b: Ptr) {
+    self.0.link(a, b);
+    self.1.link(a, b);
+  }
+
+  fn alloc(&mut self, loc: Val) {
+    self.0.alloc(loc);
+    self.1.alloc(loc);
+  }
+}
+
+// Observes with a shared observer, so it can be read after the reduction.
+impl<T: Observer> Observer for Arc<Mutex<T>> {
+  fn interact(&mut self, kind: Kind, a: Ptr, b: Ptr) {
+    self.lock().unwrap().interact(kind, a, b);
+  }
+
+  fn call(&mut self, id: Val, locs: &[Val]) {
+    self.lock().unwrap().call(id, locs);
+  }
+
+  fn link(&mut self, a: Ptr, b: Ptr) {
+    self.lock().unwrap().link(a, b);
+  }
+
+  fn alloc(&mut self, loc: Val) {
+    self.lock().unwrap().alloc(loc);
+  }
+}
+
+// The cost of a def, as counted by a Profile.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DefCost {
+  pub calls: usize, // times it was expanded
+  pub nodes: usize, // nodes loaded by those expansions
+  pub rewrites: usize, // interactions on nodes it owns
+}
+
+// Profiles a reduction by def. Each node is owned by the def whose expansion loaded it, and nodes
+// allocated by an interaction are owned by the owner of the interacting nodes. An interaction
+// counts once for each def owning one of its two nodes.
+#[derive(Clone, Debug)]
+pub struct Profile {
+  pub defs: HashMap<Val, DefCost>,
+  pub owner: Vec<Val>, // owner of each node, or NONE
+  pub cur: Val, // owner of the interaction being performed
+}
+
+// Marks nodes owned by no def, such as the ones of the initial net.
+const NONE: Val = Val::MAX;
+
+// The outcome of a fuel-limited reduction.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Status {
+  Normal, // the net reached normal form
+  OutOfFuel, // the fuel ran out, and the net can be reduced further
+}
+
+// An allocation failed, since the heap (or the memory cap) has no room for more nodes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct OutOfMemory {
+  pub used: usize, // nodes in use
+  pub size: usize, // nodes available
+}
+
+// The redexes offered by each thread to idle threads, when reducing in parallel.
+pub struct Bags {
+  pub bags: Vec<Mutex<Vec<(Ptr, Ptr)>>>,
+  pub idle: AtomicUsize, // idle threads
+  pub halt: AtomicBool, // a thread failed, so all stop
 }
 
 // A compact closed net, used for dereferences.
@@ -88,13 +288,17 @@ pub struct Net {
 pub struct Def {
   pub rdex: Vec<(Ptr, Ptr)>,
   pub node: Vec<(Ptr, Ptr)>,
+  pub labs: Vec<Lab>, // label of each node, or empty if no CT5 node points to it
 }
 
-pub type CallNative = Arc<dyn Fn(&Net, &Book, Ptr, Ptr) -> bool>;
+pub type CallNative = Arc<dyn Fn(&Net, &Book, Ptr, Ptr) -> bool + Send + Sync>;
 
-// A map of id to definitions (closed nets).
+// A map of id to definitions (closed nets). Ids are given to names in order, by 'intern', so the
+// defs are a table only as long as the highest id defined.
 pub struct Book {
   pub defs: Vec<Def>,
+  pub names: Vec<String>, // the name of each id
+  pub ids: HashMap<String, Val>,
   pub call_native: CallNative,
 }
 
@@ -102,6 +306,56 @@ pub fn call_native() -> CallNative {
   Arc::new(|_, _, _, _| false)
 }
 
+// Reads a number as two's complement.
+#[inline(always)]
+pub const fn signed(val: Val) -> IVal {
+  return ((val << (Val::BITS - NUM_BITS)) as IVal) >> (Val::BITS - NUM_BITS);
+}
+
+// Stores a signed number as two's complement.
+#[inline(always)]
+pub const fn unsigned(val: IVal) -> Val {
+  return (val as Val) & NUM_MASK;
+}
+
+#[inline(always)]
+pub const fn is_float(val: Val) -> bool {
+  return (val >> NUM_BITS) & OPR_MASK == FLOAT;
+}
+
+// Reads a number as a float.
+#[inline(always)]
+pub fn to_float(val: Val) -> FVal {
+  return FVal::from_bits(val << (Val::BITS - NUM_BITS));
+}
+
+// Stores a float, rounding it to the nearest one that fits.
+#[inline(always)]
+pub fn from_float(val: FVal) -> Val {
+  let bits = val.to_bits();
+  let bits = if val.is_finite() { bits + (1 << (Val::BITS - NUM_BITS - 1)) } else { bits };
+  return (FLOAT << NUM_BITS) | (bits >> (Val::BITS - NUM_BITS));
+}
+
+// Float arithmetic and comparisons. Other operations treat floats as integers.
+#[inline(always)]
+fn op_float(opr: Tag, a: FVal, b: FVal) -> Option<Val> {
+  match opr {
+    ADD => Some(from_float(a + b)),
+    SUB => Some(from_float(a - b)),
+    MUL => Some(from_float(a * b)),
+    DIV => Some(from_float(a / b)),
+    MOD => Some(from_float(a % b)),
+    EQ  => Some((a == b) as Val),
+    NE  => Some((a != b) as Val),
+    LT  => Some((a < b) as Val),
+    GT  => Some((a > b) as Val),
+    LTE => Some((a <= b) as Val),
+    GTE => Some((a >= b) as Val),
+    _   => None,
+  }
+}
+
 impl Ptr {
   #[inline(always)]
   pub const fn new(tag: Tag, val: Val) -> Self {
@@ -133,6 +387,11 @@ impl Ptr {
     return matches!(self.tag(), VR1..=VR2);
   }
 
+  #[inline(always)]
+  pub fn is_red(&self) -> bool {
+    return matches!(self.tag(), RD1..=RD2);
+  }
+
   #[inline(always)]
   pub fn is_era(&self) -> bool {
     return matches!(self.tag(), ERA);
@@ -193,6 +452,18 @@ impl Ptr {
     //return Ptr::new(self.tag(), self.val() + if self.has_loc() { loc - 1 } else { 0 });
   //}
 
+  // Converts a variable into a redirection to the same port.
+  #[inline(always)]
+  pub fn redirect(&self) -> Ptr {
+    return Ptr::new(self.tag() + RD1 - VR1, self.val());
+  }
+
+  // Converts a redirection back into a variable.
+  #[inline(always)]
+  pub fn unredirect(&self) -> Ptr {
+    return Ptr::new(self.tag() + VR1 - RD1, self.val());
+  }
+
   // Can this redex be skipped (as an optimization)?
   #[inline(always)]
   pub fn can_skip(a: Ptr, b: Ptr) -> bool {
@@ -202,7 +473,7 @@ impl Ptr {
 
 impl APtr {
   pub fn new(ptr: Ptr) -> Self {
-    APtr(AtomicU32::new(ptr.0))
+    APtr(AVal::new(ptr.0))
   }
 
   pub fn load(&self) -> Ptr {
@@ -212,6 +483,14 @@ impl APtr {
   pub fn store(&self, ptr: Ptr) {
     self.0.store(ptr.0, Ordering::Relaxed);
   }
+
+  pub fn swap(&self, ptr: Ptr) -> Ptr {
+    Ptr(self.0.swap(ptr.0, Ordering::AcqRel))
+  }
+
+  pub fn cas(&self, old: Ptr, new: Ptr) -> bool {
+    self.0.compare_exchange(old.0, new.0, Ordering::AcqRel, Ordering::Acquire).is_ok()
+  }
 }
 
 
@@ -219,13 +498,42 @@ impl Book {
   #[inline(always)]
   pub fn new() -> Self {
     Book {
-      defs: vec![Def::new(); 1 << 24],
+      defs: vec![],
+      names: vec![],
+      ids: HashMap::new(),
       call_native: call_native(),
     }
   }
 
+  // The id of a name, giving it the next one if it has none.
+  pub fn intern(&mut self, name: &str) -> Val {
+    if let Some(id) = self.ids.get(name) {
+      return *id;
+    }
+    let id = self.names.len() as Val;
+    assert!(id <= DEF_MASK, "too many def names");
+    self.names.push(name.to_string());
+    self.ids.insert(name.to_string(), id);
+    return id;
+  }
+
+  pub fn id(&self, name: &str) -> Option<Val> {
+    self.ids.get(name).copied()
+  }
+
+  // The name of an id, or the id itself if it has none (e.g., on a native-only book).
+  pub fn name(&self, id: Val) -> String {
+    match self.names.get(id as usize) {
+      Some(name) => name.clone(),
+      None => format!("{}", id),
+    }
+  }
+
   #[inline(always)]
   pub fn def(&mut self, id: Val, def: Def) {
+    if self.defs.len() <= id as usize {
+      self.defs.resize(id as usize + 1, Def::new());
+    }
     self.defs[id as usize] = def;
   }
 
@@ -233,24 +541,377 @@ impl Book {
   pub fn get(&self, id: Val) -> Option<&Def> {
     self.defs.get(id as usize)
   }
+
+  // Writes the defined defs to a versioned binary checkpoint. Native functions aren't saved.
+  pub fn save(&self, out: &mut impl Write) -> std::io::Result<()> {
+    write_header(out, BOOK_MAGIC)?;
+    let defs: Vec<_> = self.defs.iter().enumerate().filter(|(_, def)| def.node.len() > 0).collect();
+    write_u64(out, defs.len() as u64)?;
+    for (id, def) in defs {
+      write_u32(out, id as u32)?;
+      write_ptrs(out, &def.rdex)?;
+      write_ptrs(out, &def.node)?;
+      write_labs(out, &def.labs)?;
+    }
+    write_u64(out, self.names.len() as u64)?;
+    for name in &self.names {
+      write_u64(out, name.len() as u64)?;
+      out.write_all(name.as_bytes())?;
+    }
+    return Ok(());
+  }
+
+  // Reads a book written by 'save', with the interpreter's native functions.
+  pub fn load(inp: &mut impl Read) -> std::io::Result<Book> {
+    read_header(inp, BOOK_MAGIC)?;
+    let mut book = Book::new();
+    for _ in 0 .. read_u64(inp)? {
+      let id = read_u32(inp)? as Val;
+      if id > DEF_MASK {
+        return Err(invalid_data("def id out of range"));
+      }
+      let rdex = read_ptrs(inp)?;
+      let node = read_ptrs(inp)?;
+      let labs = read_labs(inp)?;
+      if labs.len() > 0 && labs.len() != node.len() {
+        return Err(invalid_data("def labels don't match its nodes"));
+      }
+      book.def(id, Def { rdex, node, labs });
+    }
+    for i in 0 .. read_u64(inp)? {
+      // Reads through 'take', so a bad length is an error rather than a huge allocation.
+      let len = read_u64(inp)?;
+      let mut name = vec![];
+      inp.by_ref().take(len).read_to_end(&mut name)?;
+      if name.len() as u64 != len {
+        return Err(invalid_data("def name is cut short"));
+      }
+      let name = String::from_utf8(name).map_err(|_| invalid_data("def name isn't UTF-8"))?;
+      if book.ids.contains_key(&name) || i > DEF_MASK as u64 {
+        return Err(invalid_data("def names aren't unique"));
+      }
+      book.intern(&name);
+    }
+    return Ok(book);
+  }
+}
+
+impl Default for Book {
+  fn default() -> Self {
+    Book::new()
+  }
+}
+
+impl Bags {
+  pub fn new(tids: usize) -> Self {
+    Bags {
+      bags: (0 .. tids).map(|_| Mutex::new(vec![])).collect(),
+      idle: AtomicUsize::new(0),
+      halt: AtomicBool::new(false),
+    }
+  }
+
+  // Moves the oldest half of a thread's redexes to its bag, if the previous ones were taken.
+  pub fn offer(&self, tid: usize, rdex: &mut Vec<(Ptr, Ptr)>) {
+    if let Ok(mut bag) = self.bags[tid].try_lock() {
+      if bag.is_empty() {
+        bag.extend(rdex.drain(.. rdex.len() / 2));
+      }
+    }
+  }
+
+  // Takes the redexes on the first non-empty bag, starting from the thread's own.
+  pub fn steal(&self, tid: usize, rdex: &mut Vec<(Ptr, Ptr)>) -> bool {
+    for i in 0 .. self.bags.len() {
+      let mut bag = self.bags[(tid + i) % self.bags.len()].lock().unwrap();
+      if !bag.is_empty() {
+        rdex.append(&mut bag);
+        return true;
+      }
+    }
+    return false;
+  }
+
+  // Are there redexes on any bag?
+  pub fn any(&self) -> bool {
+    return self.bags.iter().any(|bag| !bag.lock().unwrap().is_empty());
+  }
+}
+
+impl Kind {
+  // The kind of the interaction between two pointers.
+  pub fn of(a: Ptr, b: Ptr) -> Kind {
+    match (a.tag(), b.tag()) {
+      (REF, OP2..) | (OP2.., REF) => Kind::Dref,
+      (VR1 ..= VR2, _) | (_, VR1 ..= VR2) => Kind::Link,
+      (CT0.., CT0..) if a.tag() == b.tag() => Kind::Anni, // see Heap::kind, for CT5s
+      (ERA, _) | (_, ERA) | (REF, _) | (_, REF) | (NUM, NUM) => Kind::Eras,
+      (OP2 ..= MAT, NUM) | (NUM, OP2 ..= MAT) => Kind::Oper,
+      _ => Kind::Comm,
+    }
+  }
+
+  // Whether 'Net::interact' has a rule for two pointers. It panics on the others, like a REF with a
+  // NUM, which no valid net has.
+  pub fn has_rule(a: Ptr, b: Ptr) -> bool {
+    matches!((a.tag(), b.tag()),
+      (REF, OP2..) | (OP2.., REF)
+      | (VR1 ..= VR2, _) | (_, VR1 ..= VR2)
+      | (ERA | REF | NUM, ERA) | (ERA, REF | NUM) | (NUM, NUM)
+      | (CT0.., CT0.. | ERA | NUM) | (ERA | NUM, CT0..)
+      | (OP2 ..= MAT, ERA | NUM | CT0..) | (ERA | NUM | CT0.., OP2 ..= MAT)
+    )
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Kind::Anni => "anni",
+      Kind::Comm => "comm",
+      Kind::Eras => "eras",
+      Kind::Dref => "dref",
+      Kind::Oper => "oper",
+      Kind::Link => "link",
+    }
+  }
+}
+
+impl DefCost {
+  // What a def is ranked by: its expansions and the rewrites on its nodes.
+  pub fn total(&self) -> usize {
+    return self.calls + self.rewrites;
+  }
+}
+
+impl Profile {
+  pub fn new() -> Self {
+    Profile { defs: HashMap::new(), owner: vec![], cur: NONE }
+  }
+
+  // The defs with the highest cost, most costly first.
+  pub fn top(&self, n: usize) -> Vec<(Val, DefCost)> {
+    let mut defs: Vec<_> = self.defs.iter().map(|(id, cost)| (*id, *cost)).collect();
+    defs.sort_by_key(|(id, cost)| (std::cmp::Reverse(cost.total()), *id));
+    defs.truncate(n);
+    return defs;
+  }
+
+  fn owner_of(&self, ptr: Ptr) -> Val {
+    if ptr.has_loc() {
+      return self.owner.get(ptr.val() as usize).copied().unwrap_or(NONE);
+    } else {
+      return NONE;
+    }
+  }
+
+  fn own(&mut self, loc: Val, id: Val) {
+    if self.owner.len() <= loc as usize {
+      self.owner.resize(loc as usize + 1, NONE);
+    }
+    self.owner[loc as usize] = id;
+  }
+}
+
+impl Default for Profile {
+  fn default() -> Self {
+    Profile::new()
+  }
 }
 
+impl Observer for Profile {
+  fn interact(&mut self, kind: Kind, a: Ptr, b: Ptr) {
+    let (a, b) = (self.owner_of(a), self.owner_of(b));
+    if a != NONE {
+      self.defs.entry(a).or_default().rewrites += 1;
+    }
+    if b != NONE && b != a {
+      self.defs.entry(b).or_default().rewrites += 1;
+    }
+    self.cur = if a != NONE { a } else { b };
+  }
+
+  fn call(&mut self, id: Val, locs: &[Val]) {
+    let cost = self.defs.entry(id).or_default();
+    cost.calls += 1;
+    cost.nodes += locs.len();
+    for loc in locs {
+      self.own(*loc, id);
+    }
+  }
+
+  fn alloc(&mut self, loc: Val) {
+    self.own(loc, self.cur);
+  }
+}
+
+impl OutOfMemory {
+  // A heap size that would give the net room to grow, or None if it's already as big as a heap gets.
+  pub fn suggested_size(&self) -> Option<usize> {
+    if self.size >= MAX_SIZE {
+      return None;
+    }
+    return Some((self.size * 2).next_power_of_two().min(MAX_SIZE));
+  }
+}
+
+impl std::fmt::Display for OutOfMemory {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self.suggested_size() {
+      Some(size) => write!(f, "out of memory: {} of {} nodes in use (try a size of {} nodes)", self.used, self.size, size),
+      None => write!(f, "out of memory: {} of {} nodes in use (the limit of {} nodes was reached)", self.used, self.size, MAX_SIZE),
+    }
+  }
+}
+
+impl std::error::Error for OutOfMemory {}
+
 impl Def {
   pub fn new() -> Self {
     Def {
       rdex: vec![],
       node: vec![],
+      labs: vec![],
     }
   }
 }
 
+impl Default for Def {
+  fn default() -> Self {
+    Def::new()
+  }
+}
+
+// Allocates a zeroed segment. Only for types where all zeroes is a valid value.
+unsafe fn zeroed<T>(len: usize) -> Vec<T> {
+  let layout = std::alloc::Layout::array::<T>(len).unwrap();
+  let data = std::alloc::alloc_zeroed(layout) as *mut T;
+  if data.is_null() {
+    std::alloc::handle_alloc_error(layout);
+  }
+  return Vec::from_raw_parts(data, len, len);
+}
+
+// The base pointers point to segments owned by 'segs' and 'labs', which are shared by all threads.
+unsafe impl Send for Heap {}
+unsafe impl Sync for Heap {}
+
 impl Heap {
   pub fn new(size: usize) -> Heap {
-    let mut data = vec![];
-    for _ in 0..size {
-      data.push((APtr::new(NULL), APtr::new(NULL)));
+    assert!(size <= MAX_SIZE, "a heap can't have more than {} nodes", MAX_SIZE);
+    let mut heap = Heap {
+      segs: Arc::new(vec![]),
+      base: [std::ptr::null(); 32],
+      labs: Arc::new(vec![]),
+      lbase: [std::ptr::null(); 32],
+      size,
+      len: 0,
+      head: vec![],
+    };
+    heap.grow();
+    return heap;
+  }
+
+  // Returns a handle to the same nodes, to be used by another thread.
+  pub fn share(&self) -> Heap {
+    return Heap {
+      segs: Arc::clone(&self.segs),
+      base: self.base,
+      labs: Arc::clone(&self.labs),
+      lbase: self.lbase,
+      size: self.size,
+      len: self.len,
+      head: vec![],
+    };
+  }
+
+  // Allocates the next segment. Returns false if the heap is already at its max size.
+  pub fn grow(&mut self) -> bool {
+    if self.len >= self.size {
+      return false;
+    }
+    let len = (self.len * 2).max(SEG0).min(self.size);
+    // Zeroed memory is a segment of NULLs, and the OS only commits its pages once they're touched.
+    let segs = Arc::get_mut(&mut self.segs).expect("can't grow a shared heap");
+    segs.push(unsafe { zeroed(len - self.len) });
+    self.base[segs.len() - 1] = segs[segs.len() - 1].as_ptr().wrapping_sub(self.len);
+    let labs = Arc::get_mut(&mut self.labs).expect("can't grow a shared heap");
+    labs.push(unsafe { zeroed(len - self.len) });
+    self.lbase[labs.len() - 1] = labs[labs.len() - 1].as_ptr().wrapping_sub(self.len);
+    if self.head.len() > 0 {
+      self.head.extend((self.len .. len).map(|_| APtr::new(NULL)));
+    }
+    self.len = len;
+    return true;
+  }
+
+  // Frees the segments past the first 'len' nodes, except the first one.
+  pub fn shrink_to(&mut self, len: usize) {
+    let segs = Arc::get_mut(&mut self.segs).expect("can't shrink a shared heap");
+    let labs = Arc::get_mut(&mut self.labs).expect("can't shrink a shared heap");
+    while segs.len() > 1 && SEG0 << (segs.len() - 2) >= len {
+      segs.pop();
+      labs.pop();
+      self.len = SEG0 << (segs.len() - 1);
+    }
+    self.head.truncate(self.len);
+  }
+
+  // Allocates segments until the heap has at least 'len' nodes.
+  pub fn grow_to(&mut self, len: usize) {
+    while self.len < len && self.grow() {}
+  }
+
+  // Finds the node at an index. Segment 0 holds indices below SEG0, and segment 'k' holds the ones
+  // below 'SEG0 << k'.
+  #[inline(always)]
+  fn node(&self, index: Val) -> &(APtr, APtr) {
+    let seg = (Val::BITS - (index / SEG0 as Val).leading_zeros()) as usize;
+    return unsafe { &*self.base.get_unchecked(seg).wrapping_add(index as usize) };
+  }
+
+  #[inline(always)]
+  fn lab(&self, index: Val) -> &AtomicU16 {
+    let seg = (Val::BITS - (index / SEG0 as Val).leading_zeros()) as usize;
+    return unsafe { &*self.lbase.get_unchecked(seg).wrapping_add(index as usize) };
+  }
+
+  // The label of a CT5 node.
+  #[inline(always)]
+  pub fn get_lab(&self, index: Val) -> Lab {
+    return self.lab(index).load(Ordering::Relaxed);
+  }
+
+  #[inline(always)]
+  pub fn set_lab(&self, index: Val, lab: Lab) {
+    self.lab(index).store(lab, Ordering::Relaxed);
+  }
+
+  // The label of the con node a pointer points to.
+  #[inline(always)]
+  pub fn label(&self, ptr: Ptr) -> Lab {
+    if ptr.tag() == CT5 {
+      return self.get_lab(ptr.val());
+    } else {
+      return (ptr.tag() - CT0) as Lab;
+    }
+  }
+
+  // The kind of an interaction. Unlike Kind::of, tells apart CT5 nodes with different labels.
+  pub fn kind(&self, a: Ptr, b: Ptr) -> Kind {
+    match Kind::of(a, b) {
+      Kind::Anni if self.label(a) != self.label(b) => Kind::Comm,
+      kind => kind,
+    }
+  }
+
+  // A pointer to a con node with a label, which is stored on the node if it doesn't fit the tag.
+  #[inline(always)]
+  pub fn ctr(&self, lab: Lab, index: Val) -> Ptr {
+    if lab < LABS {
+      return Ptr::new(CT0 + lab as Tag, index);
+    } else {
+      self.set_lab(index, lab);
+      return Ptr::new(CT5, index);
     }
-    return Heap { data };
   }
 
   #[inline(always)]
@@ -265,25 +926,51 @@ impl Heap {
 
   #[inline(always)]
   pub fn get(&self, index: Val, port: Port) -> Ptr {
-    unsafe {
-      let node = self.data.get_unchecked(index as usize);
-      if port == P1 {
-        return node.0.load();
-      } else {
-        return node.1.load();
-      }
+    let node = self.node(index);
+    if port == P1 {
+      return node.0.load();
+    } else {
+      return node.1.load();
     }
   }
 
   #[inline(always)]
   pub fn set(&self, index: Val, port: Port, value: Ptr) {
-    unsafe {
-      let node = self.data.get_unchecked(index as usize);
-      if port == P1 {
-        node.0.store(value);
-      } else {
-        node.1.store(value);
-      }
+    let node = self.node(index);
+    if port == P1 {
+      node.0.store(value);
+    } else {
+      node.1.store(value);
+    }
+  }
+
+  #[inline(always)]
+  pub fn get_head(&self, index: Val) -> Ptr {
+    unsafe { self.head.get_unchecked(index as usize) }.load()
+  }
+
+  #[inline(always)]
+  pub fn set_head(&self, index: Val, value: Ptr) {
+    unsafe { self.head.get_unchecked(index as usize) }.store(value);
+  }
+
+  #[inline(always)]
+  pub fn swap(&self, index: Val, port: Port, value: Ptr) -> Ptr {
+    let node = self.node(index);
+    if port == P1 {
+      return node.0.swap(value);
+    } else {
+      return node.1.swap(value);
+    }
+  }
+
+  #[inline(always)]
+  pub fn cas(&self, index: Val, port: Port, old: Ptr, new: Ptr) -> bool {
+    let node = self.node(index);
+    if port == P1 {
+      return node.0.cas(old, new);
+    } else {
+      return node.1.cas(old, new);
     }
   }
 
@@ -304,13 +991,18 @@ impl Net {
     Net {
       rdex: vec![],
       heap: Heap::new(size),
-      locs: vec![0; 1 << 16],
+      locs: vec![],
       next: 1,
+      area: (0, size),
+      tids: 1,
+      cap: None,
+      hold: HashMap::new(),
       anni: 0,
       comm: 0,
       eras: 0,
       dref: 0,
       oper: 0,
+      observer: None,
     }
   }
 
@@ -325,63 +1017,191 @@ impl Net {
   }
 
   // Converts to a def.
-  pub fn to_def(self) -> Def {
+  pub fn to_def(mut self) -> Def {
+    self.compact();
     let mut node = vec![];
-    let mut rdex = vec![];
-    for i in 0 .. self.heap.data.len() {
-      let p1 = self.heap.get(node.len() as Val, P1);
-      let p2 = self.heap.get(node.len() as Val, P2);
-      if p1 != NULL || p2 != NULL {
-        node.push((p1, p2));
-      } else {
-        break;
+    for i in 0 .. self.next {
+      node.push((self.heap.get(i as Val, P1), self.heap.get(i as Val, P2)));
+    }
+    // Only keeps the labels of CT5 nodes, which are never 0.
+    let mut labs = vec![0; node.len()];
+    for (a, b) in node.iter().chain(&self.rdex) {
+      for ptr in [a, b] {
+        if ptr.tag() == CT5 {
+          labs[ptr.val() as usize] = self.heap.get_lab(ptr.val());
+        }
       }
     }
-    for i in 0 .. self.rdex.len() {
-      let p1 = self.rdex[i].0;
-      let p2 = self.rdex[i].1;
-      rdex.push((p1, p2));
+    if labs.iter().all(|lab| *lab == 0) {
+      labs.clear();
     }
-    return Def { rdex, node };
+    return Def { rdex: self.rdex, node, labs };
   }
 
   // Reads back from a def.
   pub fn from_def(def: Def) -> Self {
     let mut net = Net::new(def.node.len());
+    net.heap.grow_to(def.node.len());
     for (i, &(p1, p2)) in def.node.iter().enumerate() {
       net.heap.set(i as Val, P1, p1);
       net.heap.set(i as Val, P2, p2);
     }
+    for (i, &lab) in def.labs.iter().enumerate() {
+      net.heap.set_lab(i as Val, lab);
+    }
+    net.next = def.node.len();
     net.rdex = def.rdex;
     net
   }
 
+  // Moves the live nodes (the ones reachable from the root and the redexes) to a dense prefix of the
+  // heap, keeping their order, and frees everything else. Only for nets that own their heap.
+  pub fn compact(&mut self) {
+    let mut live = vec![false; self.heap.len];
+    let mut olds = vec![0]; // old index of each live node
+    let mut visit = vec![self.heap.get_root()];
+    for (a, b) in &self.rdex {
+      visit.push(*a);
+      visit.push(*b);
+    }
+    while let Some(ptr) = visit.pop() {
+      let loc = ptr.val();
+      if (ptr.has_loc() || ptr.is_red()) && loc != 0 && !live[loc as usize] {
+        live[loc as usize] = true;
+        olds.push(loc);
+        visit.push(self.heap.get(loc, P1));
+        visit.push(self.heap.get(loc, P2));
+      }
+    }
+    olds.sort_unstable();
+    let mut locs = vec![0; self.heap.len]; // new index of each old node
+    for (i, loc) in olds.iter().enumerate() {
+      locs[*loc as usize] = i as Val;
+    }
+    let adjust = |ptr: Ptr| {
+      if ptr.has_loc() || ptr.is_red() {
+        return Ptr::new(ptr.tag(), locs[ptr.val() as usize]);
+      } else {
+        return ptr;
+      }
+    };
+    let node: Vec<_> = olds.iter().map(|loc| (adjust(self.heap.get(*loc, P1)), adjust(self.heap.get(*loc, P2)))).collect();
+    let labs: Vec<_> = olds.iter().map(|loc| self.heap.get_lab(*loc)).collect();
+    for (a, b) in &mut self.rdex {
+      *a = adjust(*a);
+      *b = adjust(*b);
+    }
+    self.heap.shrink_to(node.len());
+    for i in node.len() .. self.heap.len {
+      // Only writes to used slots, so that pages never touched aren't committed.
+      if !self.heap.get(i as Val, P1).is_nil() || !self.heap.get(i as Val, P2).is_nil() {
+        self.free(i as Val);
+      }
+    }
+    for (i, (p1, p2)) in node.iter().enumerate() {
+      self.heap.set(i as Val, P1, *p1);
+      self.heap.set(i as Val, P2, *p2);
+      self.heap.set_lab(i as Val, labs[i]);
+    }
+    self.next = node.len();
+  }
+
   #[inline(always)]
-  pub fn alloc(&mut self, size: usize) -> Val {
-    let len = self.heap.data.len();
-    // On the first pass, just alloc without looking.
+  pub fn alloc(&mut self, size: usize) -> Result<Val, OutOfMemory> {
+    // When sharing the heap, search for an available slot in our own area.
+    if self.tids > 1 {
+      let (ini, end) = self.area;
+      for _ in ini .. end {
+        let index = (ini + self.next % (end - ini)) as Val;
+        self.next += 1;
+        if self.heap.get(index, P1).is_nil() && self.heap.get(index, P2).is_nil() {
+          // Reserves it, since we may go around the area again before it is written.
+          self.reserve(index);
+          return Ok(index);
+        }
+      }
+      return Err(self.out_of_memory());
+    }
+    let len = self.limit();
+    // On the first pass, just alloc without looking, growing the heap as needed.
     if self.next < len {
+      if self.next >= self.heap.len {
+        self.heap.grow_to(self.next + 1);
+      }
       let index = self.next as Val;
       self.next += 1;
-      return index;
+      self.reserve(index);
+      return Ok(index);
     // On later passes, search for an available slot.
     } else {
-      loop {
+      self.heap.grow_to(len);
+      for _ in 0 .. len {
         self.next += 1;
-        let index = (self.next % self.heap.data.len()) as Val;
-        if self.heap.get(index, P2).is_nil() {
-          return index;
+        let index = (self.next % len) as Val;
+        if self.heap.get(index, P1).is_nil() && self.heap.get(index, P2).is_nil() {
+          // Reserves it, since a nearly full heap may be searched again before it is written.
+          self.reserve(index);
+          return Ok(index);
         }
       }
+      return Err(self.out_of_memory());
     }
   }
 
   #[inline(always)]
   pub fn free(&self, index: Val) {
-    unsafe { self.heap.data.get_unchecked(index as usize) }.0.store(NULL);
-    unsafe { self.heap.data.get_unchecked(index as usize) }.1.store(NULL);
+    // When sharing the heap, ports are cleared by the atomic links themselves.
+    if self.tids > 1 {
+      return;
+    }
+    self.heap.node(index).0.store(NULL);
+    self.heap.node(index).1.store(NULL);
+  }
+
+  // Marks a slot as taken, until its ports are written.
+  #[inline(always)]
+  fn reserve(&mut self, index: Val) {
+    self.heap.node(index).0.store(LOCK);
+    if let Some(observer) = &mut self.observer {
+      observer.alloc(index);
+    }
+  }
+
+  // Allocates many nodes at once. If it fails, the nodes taken so far are released, so the net is
+  // left as it was.
+  pub fn alloc_nodes<const N: usize>(&mut self) -> Result<[Val; N], OutOfMemory> {
+    let mut locs = [0; N];
+    for i in 0 .. N {
+      match self.alloc(1) {
+        Ok(loc) => locs[i] = loc,
+        Err(err) => {
+          self.release(&locs[0 .. i]);
+          return Err(err);
+        }
+      }
+    }
+    return Ok(locs);
+  }
+
+  // Releases nodes that were allocated, but never linked.
+  fn release(&self, locs: &[Val]) {
+    for loc in locs {
+      self.heap.set(*loc, P1, NULL);
+      self.heap.set(*loc, P2, NULL);
+    }
+  }
+
+  // The number of nodes the net may use.
+  fn limit(&self) -> usize {
+    return self.heap.size.min(self.cap.unwrap_or(usize::MAX));
   }
 
+  // Counts the nodes in use. Only called on failure, since it scans the whole heap.
+  fn out_of_memory(&self) -> OutOfMemory {
+    let size = self.limit();
+    let used = (0 .. self.heap.len.min(size) as Val).filter(|i| !self.heap.get(*i, P1).is_nil() || !self.heap.get(*i, P2).is_nil()).count();
+    return OutOfMemory { used, size };
+  }
 
   // Gets a pointer's target.
   #[inline(always)]
@@ -397,6 +1217,9 @@ impl Net {
 
   // Links two pointers, forming a new wire.
   pub fn link(&mut self, a: Ptr, b: Ptr) {
+    if let Some(observer) = &mut self.observer {
+      observer.link(a, b);
+    }
     // Creates redex A-B
     if a.is_pri() && b.is_pri() {
       if Ptr::can_skip(a, b) {
@@ -416,13 +1239,164 @@ impl Net {
     }
   }
 
+  // Links the targets of two aux ports of consumed nodes.
+  #[inline(always)]
+  pub fn link_ports(&mut self, a_dir: Ptr, b_dir: Ptr) {
+    if self.tids > 1 {
+      self.atomic_link(a_dir, b_dir);
+    } else {
+      self.link(self.get_target(a_dir), self.get_target(b_dir));
+    }
+  }
+
+  // Links the target of an aux port of a consumed node to a pointer.
+  #[inline(always)]
+  pub fn link_port(&mut self, a_dir: Ptr, b_ptr: Ptr) {
+    if self.tids > 1 {
+      self.half_atomic_link(a_dir, b_ptr);
+    } else {
+      self.link(self.get_target(a_dir), b_ptr);
+    }
+  }
+
+  // Takes the target of a port we own, locking it until we store something else there.
+  #[inline(always)]
+  fn take(&self, dir: Ptr) -> Ptr {
+    loop {
+      let got = self.heap.swap(dir.val(), dir.0 & 1, LOCK);
+      if got != LOCK {
+        return got;
+      }
+      std::hint::spin_loop();
+    }
+  }
+
+  // Atomically links the targets of two aux ports of consumed nodes.
+  pub fn atomic_link(&mut self, a_dir: Ptr, b_dir: Ptr) {
+    let a_ptr = self.take(a_dir);
+    let b_ptr = self.take(b_dir);
+    // The ports were linked to each other, so the wire just vanishes.
+    if a_ptr == b_dir {
+      self.set_target(a_dir, NULL);
+      self.set_target(b_dir, NULL);
+    // Both targets are main ports: creates a redex.
+    } else if a_ptr.is_pri() && b_ptr.is_pri() {
+      self.set_target(a_dir, NULL);
+      self.set_target(b_dir, NULL);
+      self.link(a_ptr, b_ptr);
+    // One target is a main port: sends it through the other wire.
+    } else if a_ptr.is_pri() {
+      self.set_target(a_dir, NULL);
+      self.set_target(b_dir, a_ptr);
+      self.atomic_link_pri(b_dir, b_ptr);
+    } else if b_ptr.is_pri() {
+      self.set_target(b_dir, NULL);
+      self.set_target(a_dir, b_ptr);
+      self.atomic_link_pri(a_dir, a_ptr);
+    // Both targets are aux ports: leaves redirections, so that each wire continues on the other.
+    } else {
+      self.set_target(a_dir, b_ptr.redirect());
+      self.set_target(b_dir, a_ptr.redirect());
+      self.atomic_skip(a_dir, a_ptr, b_ptr);
+      self.atomic_skip(b_dir, b_ptr, a_ptr);
+    }
+  }
+
+  // Atomically links the target of an aux port of a consumed node to a pointer.
+  pub fn half_atomic_link(&mut self, a_dir: Ptr, b_ptr: Ptr) {
+    let a_ptr = self.take(a_dir);
+    // A fresh aux port: moves the target there, leaving a redirection behind.
+    if b_ptr.is_var() {
+      self.set_target(b_ptr, a_ptr);
+      if a_ptr.is_pri() {
+        self.set_target(a_dir, NULL);
+      } else {
+        self.set_target(a_dir, b_ptr.redirect());
+        self.atomic_skip(a_dir, a_ptr, b_ptr);
+      }
+    // Both are main ports: creates a redex.
+    } else if a_ptr.is_pri() {
+      self.set_target(a_dir, NULL);
+      self.link(a_ptr, b_ptr);
+    // Otherwise, sends the main port through the wire.
+    } else {
+      self.set_target(a_dir, b_ptr);
+      self.atomic_link_pri(a_dir, a_ptr);
+    }
+  }
+
+  // If the aux port at 'ptr' still points to the redirection at 'dir', points it to 'new' instead,
+  // and clears the redirection. Otherwise, the redirection will be cleared by whoever walks it.
+  #[inline(always)]
+  fn atomic_skip(&self, dir: Ptr, ptr: Ptr, new: Ptr) {
+    if self.heap.cas(ptr.val(), ptr.0 & 1, dir, new) {
+      self.heap.set(dir.val(), dir.0 & 1, NULL);
+    }
+  }
+
+  // Sends the main port stored on 'src' through the wire that starts at 'dir'. It moves forward,
+  // clearing redirections, until it reaches an aux port, which receives it, or another main port
+  // being sent the other way, which forms a redex with it.
+  pub fn atomic_link_pri(&mut self, src: Ptr, dir: Ptr) {
+    let pri = self.get_target(src);
+    let mut dir = dir;
+    let mut path = vec![];
+    loop {
+      let trg = self.get_target(dir);
+      // A redirection: nobody else will walk through it, so we clear it.
+      if trg.is_red() {
+        self.set_target(dir, NULL);
+        path.push(dir);
+        dir = trg.unredirect();
+        continue;
+      }
+      // An aux port: stores our main port there, then clears the path back to 'src'. That path
+      // ends on a port we already cleared, which may have been allocated again, so we stop there.
+      if trg.is_var() {
+        if self.heap.cas(dir.val(), dir.0 & 1, trg, pri) {
+          let mut bak = trg;
+          while !path.contains(&bak) && self.get_target(bak).is_red() {
+            let nxt = self.get_target(bak);
+            self.set_target(bak, NULL);
+            bak = nxt.unredirect();
+          }
+          self.set_target(src, NULL);
+          return;
+        }
+        continue;
+      }
+      // A taken port: waits until its owner stores something else there.
+      if trg == LOCK || trg.is_nil() {
+        std::hint::spin_loop();
+        continue;
+      }
+      // Another main port: both threads reach this point. The one that takes the first port (by
+      // address) creates the redex, and the other one clears both ports.
+      let (fst, snd) = if src.0 < dir.0 { (src, dir) } else { (dir, src) };
+      let fst_ptr = self.heap.swap(fst.val(), fst.0 & 1, GONE);
+      if fst_ptr != GONE {
+        let snd_ptr = self.heap.swap(snd.val(), snd.0 & 1, GONE);
+        self.link(fst_ptr, snd_ptr);
+      } else {
+        self.set_target(fst, NULL);
+        while !self.heap.cas(snd.val(), snd.0 & 1, GONE, NULL) {
+          std::hint::spin_loop();
+        }
+      }
+      return;
+    }
+  }
+
   // Performs an interaction over a redex.
-  pub fn interact(&mut self, book: &Book, a: Ptr, b: Ptr) {
+  pub fn interact(&mut self, book: &Book, a: Ptr, b: Ptr) -> Result<(), OutOfMemory> {
+    if let Some(observer) = &mut self.observer {
+      observer.interact(self.heap.kind(a, b), a, b);
+    }
     match (a.tag(), b.tag()) {
-      (REF   , OP2..) => self.call(book, a, b),
-      (OP2.. , REF  ) => self.call(book, b, a),
-      (CT0.. , CT0..) if a.tag() == b.tag() => self.anni(a, b),
-      (CT0.. , CT0..) => self.comm(a, b),
+      (REF   , OP2..) => self.call(book, a, b)?,
+      (OP2.. , REF  ) => self.call(book, b, a)?,
+      (CT0.. , CT0..) if self.heap.label(a) == self.heap.label(b) => self.anni(a, b),
+      (CT0.. , CT0..) => self.comm(a, b)?,
       (CT0.. , ERA  ) => self.era2(a),
       (ERA   , CT0..) => self.era2(b),
       (REF   , ERA  ) => self.eras += 1,
@@ -437,26 +1411,36 @@ impl Net {
       (NUM   , ERA  ) => self.eras += 1,
       (ERA   , NUM  ) => self.eras += 1,
       (NUM   , NUM  ) => self.eras += 1,
-      (OP2   , NUM  ) => self.op2n(a, b),
-      (NUM   , OP2  ) => self.op2n(b, a),
+      (OP2   , NUM  ) => self.op2n(a, b)?,
+      (NUM   , OP2  ) => self.op2n(b, a)?,
       (OP1   , NUM  ) => self.op1n(a, b),
       (NUM   , OP1  ) => self.op1n(b, a),
-      (OP2   , CT0..) => self.comm(a, b),
-      (CT0.. , OP2  ) => self.comm(b, a),
-      (OP1   , CT0..) => self.pass(a, b),
-      (CT0.. , OP1  ) => self.pass(b, a),
+      (OP2   , CT0..) => self.comm(a, b)?,
+      (CT0.. , OP2  ) => self.comm(b, a)?,
+      (OP1   , CT0..) => self.pass(a, b)?,
+      (CT0.. , OP1  ) => self.pass(b, a)?,
       (OP2   , ERA  ) => self.era2(a),
       (ERA   , OP2  ) => self.era2(b),
       (OP1   , ERA  ) => self.era1(a),
       (ERA   , OP1  ) => self.era1(b),
-      (MAT   , NUM  ) => self.mtch(a, b),
-      (NUM   , MAT  ) => self.mtch(b, a),
-      (MAT   , CT0..) => self.comm(a, b),
-      (CT0.. , MAT  ) => self.comm(b, a),
+      (MAT   , NUM  ) => self.mtch(a, b)?,
+      (NUM   , MAT  ) => self.mtch(b, a)?,
+      (MAT   , CT0..) => self.comm(a, b)?,
+      (CT0.. , MAT  ) => self.comm(b, a)?,
       (MAT   , ERA  ) => self.era2(a),
       (ERA   , MAT  ) => self.era2(b),
       _               => unreachable!(),
     };
+    return Ok(());
+  }
+
+  // A pointer to a new node, with the tag and label of another.
+  #[inline(always)]
+  fn like(&self, ptr: Ptr, loc: Val) -> Ptr {
+    if ptr.tag() == CT5 {
+      self.heap.set_lab(loc, self.heap.get_lab(ptr.val()));
+    }
+    return Ptr::new(ptr.tag(), loc);
   }
 
   pub fn conn(&mut self, a: Ptr, b: Ptr) {
@@ -468,22 +1452,15 @@ impl Net {
 
   pub fn anni(&mut self, a: Ptr, b: Ptr) {
     self.anni += 1;
-    self.link(self.heap.get(a.val(), P1), self.heap.get(b.val(), P1));
-    self.link(self.heap.get(a.val(), P2), self.heap.get(b.val(), P2));
+    self.link_ports(Ptr::new(VR1, a.val()), Ptr::new(VR1, b.val()));
+    self.link_ports(Ptr::new(VR2, a.val()), Ptr::new(VR2, b.val()));
     self.free(a.val());
     self.free(b.val());
   }
 
-  pub fn comm(&mut self, a: Ptr, b: Ptr) {
+  pub fn comm(&mut self, a: Ptr, b: Ptr) -> Result<(), OutOfMemory> {
+    let [loc0, loc1, loc2, loc3] = self.alloc_nodes()?;
     self.comm += 1;
-    let loc0 = self.alloc(1);
-    let loc1 = self.alloc(1);
-    let loc2 = self.alloc(1);
-    let loc3 = self.alloc(1);
-    self.link(self.heap.get(a.val(), P1), Ptr::new(b.tag(), loc0));
-    self.link(self.heap.get(b.val(), P1), Ptr::new(a.tag(), loc2));
-    self.link(self.heap.get(a.val(), P2), Ptr::new(b.tag(), loc1));
-    self.link(self.heap.get(b.val(), P2), Ptr::new(a.tag(), loc3));
     self.heap.set(loc0, P1, Ptr::new(VR1, loc2));
     self.heap.set(loc0, P2, Ptr::new(VR1, loc3));
     self.heap.set(loc1, P1, Ptr::new(VR2, loc2));
@@ -492,51 +1469,67 @@ impl Net {
     self.heap.set(loc2, P2, Ptr::new(VR1, loc1));
     self.heap.set(loc3, P1, Ptr::new(VR2, loc0));
     self.heap.set(loc3, P2, Ptr::new(VR2, loc1));
+    self.link_port(Ptr::new(VR1, a.val()), self.like(b, loc0));
+    self.link_port(Ptr::new(VR1, b.val()), self.like(a, loc2));
+    self.link_port(Ptr::new(VR2, a.val()), self.like(b, loc1));
+    self.link_port(Ptr::new(VR2, b.val()), self.like(a, loc3));
     self.free(a.val());
     self.free(b.val());
+    return Ok(());
   }
 
-  pub fn pass(&mut self, a: Ptr, b: Ptr) {
+  pub fn pass(&mut self, a: Ptr, b: Ptr) -> Result<(), OutOfMemory> {
+    let [loc0, loc1, loc2] = self.alloc_nodes()?;
     self.comm += 1;
-    let loc0 = self.alloc(1);
-    let loc1 = self.alloc(1);
-    let loc2 = self.alloc(1);
-    self.link(self.heap.get(a.val(), P2), Ptr::new(b.tag(), loc0));
-    self.link(self.heap.get(b.val(), P1), Ptr::new(a.tag(), loc1));
-    self.link(self.heap.get(b.val(), P2), Ptr::new(a.tag(), loc2));
     self.heap.set(loc0, P1, Ptr::new(VR2, loc1));
     self.heap.set(loc0, P2, Ptr::new(VR2, loc2));
     self.heap.set(loc1, P1, self.heap.get(a.val(), P1));
     self.heap.set(loc1, P2, Ptr::new(VR1, loc0));
     self.heap.set(loc2, P1, self.heap.get(a.val(), P1));
     self.heap.set(loc2, P2, Ptr::new(VR2, loc0));
+    self.heap.set(a.val(), P1, NULL);
+    self.link_port(Ptr::new(VR2, a.val()), self.like(b, loc0));
+    self.link_port(Ptr::new(VR1, b.val()), self.like(a, loc1));
+    self.link_port(Ptr::new(VR2, b.val()), self.like(a, loc2));
     self.free(a.val());
     self.free(b.val());
+    return Ok(());
   }
 
   pub fn copy(&mut self, a: Ptr, b: Ptr) {
     self.comm += 1;
-    self.link(self.heap.get(a.val(), P1), b);
-    self.link(self.heap.get(a.val(), P2), b);
+    self.link_port(Ptr::new(VR1, a.val()), b);
+    self.link_port(Ptr::new(VR2, a.val()), b);
     self.free(a.val());
   }
 
   pub fn era2(&mut self, a: Ptr) {
     self.eras += 1;
-    self.link(self.heap.get(a.val(), P1), ERAS);
-    self.link(self.heap.get(a.val(), P2), ERAS);
+    self.link_port(Ptr::new(VR1, a.val()), ERAS);
+    self.link_port(Ptr::new(VR2, a.val()), ERAS);
     self.free(a.val());
   }
 
   pub fn era1(&mut self, a: Ptr) {
     self.eras += 1;
-    self.link(self.heap.get(a.val(), P2), ERAS);
+    self.heap.set(a.val(), P1, NULL);
+    self.link_port(Ptr::new(VR2, a.val()), ERAS);
     self.free(a.val());
   }
 
-  pub fn op2n(&mut self, a: Ptr, b: Ptr) {
-    self.oper += 1;
+  pub fn op2n(&mut self, a: Ptr, b: Ptr) -> Result<(), OutOfMemory> {
     let mut p1 = self.heap.get(a.val(), P1);
+    // When sharing the heap, P1 can't hold the number while its wire is being linked, so the
+    // number goes to a new OP1 node instead.
+    if self.tids > 1 {
+      let loc0 = self.alloc(1)?;
+      self.oper += 1;
+      self.heap.set(loc0, P1, b);
+      self.link_port(Ptr::new(VR2, a.val()), Ptr::new(VR2, loc0));
+      self.link_port(Ptr::new(VR1, a.val()), Ptr::new(OP1, loc0));
+      return Ok(());
+    }
+    self.oper += 1;
     // Optimization: perform chained ops at once
     if p1.is_num() {
       let mut rt = b.val();
@@ -564,90 +1557,122 @@ impl Net {
         break;
       }
       self.link(Ptr::new(NUM, rt), p2);
-      return;
+      return Ok(());
     }
     self.heap.set(a.val(), P1, b);
     self.link(Ptr::new(OP1, a.val()), p1);
+    return Ok(());
   }
 
   pub fn op1n(&mut self, a: Ptr, b: Ptr) {
     self.oper += 1;
     let p1 = self.heap.get(a.val(), P1);
-    let p2 = self.heap.get(a.val(), P2);
     let v0 = p1.val() as Val;
     let v1 = b.val() as Val;
     let v2 = self.op(v0, v1);
-    self.link(Ptr::new(NUM, v2), p2);
+    self.heap.set(a.val(), P1, NULL);
+    self.link_port(Ptr::new(VR2, a.val()), Ptr::new(NUM, v2));
     self.free(a.val());
   }
 
   #[inline(always)]
   pub fn op(&self, a: Val, b: Val) -> Val {
-    let a_opr = (a >> 24) & 0xF;
-    let b_opr = (b >> 24) & 0xF; // not used yet
-    let a_val = a & 0xFFFFFF;
-    let b_val = b & 0xFFFFFF;
+    let a_opr = (a >> NUM_BITS) & OPR_MASK;
+    let a_val = a & NUM_MASK;
+    let b_val = b & NUM_MASK;
+    // Loading an operation drops the float mark of the left operand, so it's read the way the right
+    // one is. A float as the operation itself is an unknown one.
+    if is_float(b) && a_opr != USE as Val {
+      if let Some(val) = op_float(a_opr as Tag, to_float(a_val), to_float(b_val)) {
+        return val;
+      }
+    }
     match a_opr as Tag {
-      USE => { ((a_val & 0xF) << 24) | b_val }
-      ADD => { (a_val.wrapping_add(b_val)) & 0xFFFFFF }
-      SUB => { (a_val.wrapping_sub(b_val)) & 0xFFFFFF }
-      MUL => { (a_val.wrapping_mul(b_val)) & 0xFFFFFF }
-      DIV => { if b_val == 0 { 0xFFFFFF } else { (a_val.wrapping_div(b_val)) & 0xFFFFFF } }
-      MOD => { (a_val.wrapping_rem(b_val)) & 0xFFFFFF }
-      EQ  => { ((a_val == b_val) as Val) & 0xFFFFFF }
-      NE  => { ((a_val != b_val) as Val) & 0xFFFFFF }
-      LT  => { ((a_val < b_val) as Val) & 0xFFFFFF }
-      GT  => { ((a_val > b_val) as Val) & 0xFFFFFF }
-      AND => { (a_val & b_val) & 0xFFFFFF }
-      OR  => { (a_val | b_val) & 0xFFFFFF }
-      XOR => { (a_val ^ b_val) & 0xFFFFFF }
-      NOT => { (!b_val) & 0xFFFFFF }
-      LSH => { (a_val << b_val) & 0xFFFFFF }
-      RSH => { (a_val >> b_val) & 0xFFFFFF }
-      _   => { unreachable!() }
-    }
-  }
-
-  pub fn mtch(&mut self, a: Ptr, b: Ptr) {
-    self.oper += 1;
-    let p1 = self.heap.get(a.val(), P1); // branch
-    let p2 = self.heap.get(a.val(), P2); // return
+      USE  => { ((a_val & OPR_MASK) << NUM_BITS) | b_val }
+      ADD  => { (a_val.wrapping_add(b_val)) & NUM_MASK }
+      SUB  => { (a_val.wrapping_sub(b_val)) & NUM_MASK }
+      MUL  => { (a_val.wrapping_mul(b_val)) & NUM_MASK }
+      DIV  => { a_val.checked_div(b_val).unwrap_or(NUM_MASK) }
+      MOD  => { a_val.checked_rem(b_val).unwrap_or(a_val) }
+      EQ   => { (a_val == b_val) as Val }
+      NE   => { (a_val != b_val) as Val }
+      LT   => { (a_val < b_val) as Val }
+      GT   => { (a_val > b_val) as Val }
+      AND  => { a_val & b_val }
+      OR   => { a_val | b_val }
+      XOR  => { a_val ^ b_val }
+      NOT  => { (!b_val) & NUM_MASK }
+      LSH  => { if b_val >= NUM_BITS as Val { 0 } else { (a_val << b_val) & NUM_MASK } }
+      RSH  => { if b_val >= NUM_BITS as Val { 0 } else { a_val >> b_val } }
+      LTE  => { (a_val <= b_val) as Val }
+      GTE  => { (a_val >= b_val) as Val }
+      SDIV => { if b_val == 0 { NUM_MASK } else { (signed(a_val).wrapping_div(signed(b_val)) as Val) & NUM_MASK } }
+      SMOD => { if b_val == 0 { a_val } else { (signed(a_val).wrapping_rem(signed(b_val)) as Val) & NUM_MASK } }
+      SLT  => { (signed(a_val) < signed(b_val)) as Val }
+      SGT  => { (signed(a_val) > signed(b_val)) as Val }
+      SLTE => { (signed(a_val) <= signed(b_val)) as Val }
+      SGTE => { (signed(a_val) >= signed(b_val)) as Val }
+      SRSH => { ((signed(a_val) >> b_val.min(NUM_BITS as Val - 1)) as Val) & NUM_MASK }
+      I2F  => { from_float(signed(b_val) as FVal) }
+      F2I  => { unsigned(to_float(b_val) as IVal) }
+      _    => { 0 } // unknown operation
+    }
+  }
+
+  pub fn mtch(&mut self, a: Ptr, b: Ptr) -> Result<(), OutOfMemory> {
+    let p1 = Ptr::new(VR1, a.val()); // branch
+    let p2 = Ptr::new(VR2, a.val()); // return
     if b.val() == 0 {
-      let loc0 = self.alloc(1);
+      let loc0 = self.alloc(1)?;
+      self.oper += 1;
       self.heap.set(loc0, P2, ERAS);
-      self.link(p1, Ptr::new(CT0, loc0));
-      self.link(p2, Ptr::new(VR1, loc0));
+      self.link_port(p2, Ptr::new(VR1, loc0));
+      self.link_port(p1, Ptr::new(CT0, loc0));
       self.free(a.val());
     } else {
-      let loc0 = self.alloc(1);
-      let loc1 = self.alloc(1);
+      let [loc0, loc1] = self.alloc_nodes()?;
+      self.oper += 1;
       self.heap.set(loc0, P1, ERAS);
       self.heap.set(loc0, P2, Ptr::new(CT0, loc1));
       self.heap.set(loc1, P1, Ptr::new(NUM, b.val() - 1));
-      self.link(p1, Ptr::new(CT0, loc0));
-      self.link(p2, Ptr::new(VR2, loc1));
+      self.link_port(p2, Ptr::new(VR2, loc1));
+      self.link_port(p1, Ptr::new(CT0, loc0));
       self.free(a.val());
     }
+    return Ok(());
   }
 
   // Expands a closed net.
   #[inline(always)]
-  pub fn call(&mut self, book: &Book, ptr: Ptr, par: Ptr) {
-    self.dref += 1;
+  pub fn call(&mut self, book: &Book, ptr: Ptr, par: Ptr) -> Result<(), OutOfMemory> {
     let mut ptr = ptr;
     // FIXME: change "while" to "if" once lang prevents refs from returning refs
     if ptr.is_ref() {
-      // Intercepts with a native function, if available.
-      if self.call_native(book, ptr, par) {
-        return;
+      // Intercepts with a native function, if available. Those assume they own the heap.
+      if self.tids == 1 && self.call_native(book, ptr, par)? {
+        self.dref += 1;
+        if let Some(observer) = &mut self.observer {
+          observer.call(ptr.val(), &[]);
+        }
+        return Ok(());
       }
       // Load the closed net.
-      let got = unsafe { book.defs.get_unchecked((ptr.val() as usize) & 0xFFFFFF) };
-      if got.node.len() > 0 {
+      let ptr_id = ptr.val();
+      // Refs to undefined defs, which may be past the table, are kept as they are.
+      if let Some(got) = book.get(ptr.val() & DEF_MASK).filter(|got| got.node.len() > 0) {
         let len = got.node.len() - 1;
+        if self.locs.len() < got.node.len() {
+          self.locs.resize(got.node.len(), 0);
+        }
         // Allocates space.
         for i in 0 .. len {
-          *unsafe { self.locs.get_unchecked_mut(1 + i) } = self.alloc(1);
+          match self.alloc(1) {
+            Ok(loc) => *unsafe { self.locs.get_unchecked_mut(1 + i) } = loc,
+            Err(err) => {
+              self.release(&self.locs[1 .. 1 + i]);
+              return Err(err);
+            }
+          }
         }
         // Load nodes, adjusted.
         for i in 0 .. len {
@@ -657,6 +1682,11 @@ impl Net {
           self.heap.set(lc, P1, p1);
           self.heap.set(lc, P2, p2);
         }
+        // Load labels, if any node needs one.
+        for i in 0 .. got.labs.len().saturating_sub(1) {
+          let lc = *unsafe { self.locs.get_unchecked(1 + i) };
+          self.heap.set_lab(lc, *unsafe { got.labs.get_unchecked(1 + i) });
+        }
         // Load redexes, adjusted.
         for r in &got.rdex {
           let p1 = self.adjust(r.0);
@@ -665,9 +1695,14 @@ impl Net {
         }
         // Load root, adjusted.
         ptr = self.adjust(got.node[0].1);
+        if let Some(observer) = &mut self.observer {
+          observer.call(ptr_id, &self.locs[1 .. 1 + len]);
+        }
       }
     }
+    self.dref += 1;
     self.link(ptr, par);
+    return Ok(());
   }
 
   fn adjust(&self, ptr: Ptr) -> Ptr {
@@ -678,38 +1713,571 @@ impl Net {
     }
   }
 
-  // Reduces all redexes.
-  pub fn reduce(&mut self, book: &Book) {
+  // Reduces all redexes. If an allocation fails, the redexes not reduced yet are left on 'rdex'.
+  pub fn reduce(&mut self, book: &Book) -> Result<(), OutOfMemory> {
     let mut rdex: Vec<(Ptr, Ptr)> = vec![];
     std::mem::swap(&mut self.rdex, &mut rdex);
     while rdex.len() > 0 {
-      for (a, b) in &rdex {
-        self.interact(book, *a, *b);
+      for (i, (a, b)) in rdex.iter().enumerate() {
+        if let Err(err) = self.interact(book, *a, *b) {
+          self.put_back(&rdex[i ..]);
+          return Err(err);
+        }
       }
       rdex.clear();
       std::mem::swap(&mut self.rdex, &mut rdex);
     }
+    return Ok(());
   }
 
   // Expands heads.
-  pub fn expand(&mut self, book: &Book, dir: Ptr) {
+  pub fn expand(&mut self, book: &Book, dir: Ptr) -> Result<(), OutOfMemory> {
     let ptr = self.get_target(dir);
     if ptr.is_ctr() {
-      self.expand(book, Ptr::new(VR1, ptr.val()));
-      self.expand(book, Ptr::new(VR2, ptr.val()));
+      self.expand(book, Ptr::new(VR1, ptr.val()))?;
+      self.expand(book, Ptr::new(VR2, ptr.val()))?;
     } else if ptr.is_ref() {
-      self.call(book, ptr, dir);
+      self.call(book, ptr, dir)?;
       //self.set_target(dir, exp);
     }
+    return Ok(());
   }
 
   // Reduce a net to normal form.
-  pub fn normal(&mut self, book: &Book) {
-    self.expand(book, ROOT);
+  pub fn normal(&mut self, book: &Book) -> Result<(), OutOfMemory> {
+    self.expand(book, ROOT)?;
     while self.rdex.len() > 0 {
-      self.reduce(book);
-      self.expand(book, ROOT);
+      self.reduce(book)?;
+      self.expand(book, ROOT)?;
+    }
+    return Ok(());
+  }
+
+  // Reduces the oldest redex, expanding the root first if there are none, and returns it. Returns
+  // None once the net is in normal form. If an allocation fails, the redex is left on 'rdex'. Meant
+  // for debugging, since it's slow on nets with many redexes.
+  pub fn step(&mut self, book: &Book) -> Result<Option<(Ptr, Ptr)>, OutOfMemory> {
+    if self.rdex.len() == 0 {
+      self.expand(book, ROOT)?;
+      if self.rdex.len() == 0 {
+        return Ok(None);
+      }
+    }
+    let (a, b) = self.rdex.remove(0);
+    if let Err(err) = self.interact(book, a, b) {
+      self.put_back(&[(a, b)]);
+      return Err(err);
+    }
+    return Ok(Some((a, b)));
+  }
+
+  // Reduces a net towards normal form, like 'normal', but stops once 'fuel' rewrites were spent.
+  // Since an interaction isn't split, the last one may go slightly over it. The pending redexes
+  // stay on 'rdex', so calling it again continues where it stopped. The same holds when an
+  // allocation fails.
+  pub fn reduce_with_fuel(&mut self, book: &Book, fuel: usize) -> Result<Status, OutOfMemory> {
+    let limit = self.rewrites() + fuel;
+    loop {
+      if self.rdex.len() == 0 {
+        self.expand(book, ROOT)?;
+        if self.rdex.len() == 0 {
+          return Ok(Status::Normal);
+        }
+      }
+      let rdex = std::mem::take(&mut self.rdex);
+      for (i, (a, b)) in rdex.iter().enumerate() {
+        if self.rewrites() >= limit {
+          self.put_back(&rdex[i ..]);
+          return Ok(Status::OutOfFuel);
+        }
+        if let Err(err) = self.interact(book, *a, *b) {
+          self.put_back(&rdex[i ..]);
+          return Err(err);
+        }
+      }
+    }
+  }
+
+  // Puts back the redexes of a batch we didn't reach. Keeps the batch order: they go before the
+  // new ones.
+  fn put_back(&mut self, rest: &[(Ptr, Ptr)]) {
+    let mut rest = rest.to_vec();
+    rest.append(&mut self.rdex);
+    self.rdex = rest;
+  }
+
+  // Lazy evaluation
+  // ---------------
+
+  // Reduces a net to normal form lazily: only reduces the redexes the root depends on, and only
+  // expands refs when they're reached. Redexes nothing depends on are left on 'rdex'. If an
+  // allocation fails, all pending redexes are left on 'rdex'.
+  pub fn normal_lazy(&mut self, book: &Book) -> Result<(), OutOfMemory> {
+    self.heap.head = (0 .. self.heap.len).map(|_| APtr::new(NULL)).collect();
+    self.find_heads(ROOT);
+    for (a, b) in self.rdex.clone() {
+      for ptr in [a, b] {
+        if ptr.is_nod() {
+          self.find_heads(Ptr::new(VR1, ptr.val()));
+          self.find_heads(Ptr::new(VR2, ptr.val()));
+        }
+      }
+    }
+    let done = self.visit_lazy(book);
+    self.heap.head = vec![];
+    let mut held: Vec<_> = std::mem::take(&mut self.hold).into_iter().collect();
+    held.sort_by_key(|(loc, _)| *loc);
+    for (loc, (a, b)) in held {
+      if loc == if a.is_nod() { a.val() } else { b.val() } {
+        self.rdex.push((a, b));
+      }
     }
+    done?;
+    // Erasures never block the root, but they free memory, and never allocate, so we reduce them.
+    let mut rdex = vec![];
+    while let Some((a, b)) = self.rdex.pop() {
+      if a.is_era() || b.is_era() {
+        self.interact(book, a, b)?;
+      } else {
+        rdex.push((a, b));
+      }
+    }
+    rdex.reverse();
+    self.rdex = rdex;
+    return Ok(());
+  }
+
+  // Reduces the net lazily, walking from the root to each port it depends on.
+  fn visit_lazy(&mut self, book: &Book) -> Result<(), OutOfMemory> {
+    self.hold_rdex(book)?;
+    let mut visit = vec![ROOT];
+    while let Some(dir) = visit.pop() {
+      let ptr = self.weak_normal(book, dir)?;
+      if ptr.is_nod() {
+        // This node won't interact anymore, so walks can stop there.
+        self.heap.set_head(ptr.val(), ROOT);
+        visit.push(Ptr::new(VR2, ptr.val()));
+        visit.push(Ptr::new(VR1, ptr.val()));
+      }
+    }
+    return Ok(());
   }
 
+  // Reduces the net until the port at 'dir' is linked to a main port, and returns it. If it is
+  // linked to an aux port instead, walks up through main ports looking for the redex blocking it.
+  // When the walk reaches the root, nothing blocks it, and the aux port is returned.
+  fn weak_normal(&mut self, book: &Book, dir: Ptr) -> Result<Ptr, OutOfMemory> {
+    let mut path: Vec<Val> = vec![];
+    loop {
+      let loc = match path.last() {
+        Some(loc) => *loc,
+        None => {
+          let ptr = self.get_target(dir);
+          if ptr.is_ref() {
+            self.call_lazy(book, ptr, dir)?;
+            self.hold_rdex(book)?;
+            continue;
+          }
+          if !ptr.is_var() {
+            return Ok(ptr);
+          }
+          path.push(ptr.val());
+          ptr.val()
+        }
+      };
+      if let Some((a, b)) = self.take_held(loc) {
+        path.pop();
+        if let Err(err) = self.interact_lazy(book, a, b) {
+          self.rdex.push((a, b));
+          return Err(err);
+        }
+        self.hold_rdex(book)?;
+        continue;
+      }
+      let up = self.heap.get_head(loc);
+      if up == ROOT {
+        return Ok(self.get_target(dir));
+      }
+      path.push(up.val());
+    }
+  }
+
+  // Sets the head of every node below a port.
+  fn find_heads(&mut self, dir: Ptr) {
+    let mut visit = vec![dir];
+    while let Some(dir) = visit.pop() {
+      let ptr = self.get_target(dir);
+      if ptr.is_nod() {
+        self.heap.set_head(ptr.val(), dir);
+        visit.push(Ptr::new(VR2, ptr.val()));
+        visit.push(Ptr::new(VR1, ptr.val()));
+      }
+    }
+  }
+
+  // Reduces a redex, and updates the heads it changed. Only the ports its aux ports were linked
+  // to, and the ports of the nodes it allocated, can have been given a main port.
+  fn interact_lazy(&mut self, book: &Book, a: Ptr, b: Ptr) -> Result<(), OutOfMemory> {
+    let mut dirs = vec![];
+    for ptr in [a, b] {
+      if ptr.is_nod() {
+        dirs.extend([self.heap.get(ptr.val(), P1), self.heap.get(ptr.val(), P2)].into_iter().filter(|ptr| ptr.is_var()));
+      }
+    }
+    let next = self.next;
+    let done = self.interact(book, a, b);
+    self.update_heads(&dirs, next);
+    return done;
+  }
+
+  // Expands a ref linked to 'dir', and updates the heads it changed.
+  fn call_lazy(&mut self, book: &Book, ptr: Ptr, dir: Ptr) -> Result<(), OutOfMemory> {
+    let next = self.next;
+    let done = self.call(book, ptr, dir);
+    self.update_heads(&[dir], next);
+    return done;
+  }
+
+  // Sets the heads of the main ports linked to these ports, or to the nodes allocated since 'next'.
+  fn update_heads(&mut self, dirs: &[Ptr], next: usize) {
+    let len = self.limit();
+    let news = (next ..= self.next.min(next + len)).map(|n| (n % len) as Val).filter(|loc| (*loc as usize) < self.heap.len);
+    let news: Vec<_> = news.flat_map(|loc| [Ptr::new(VR1, loc), Ptr::new(VR2, loc)]).collect();
+    for dir in dirs.iter().chain(&news) {
+      let ptr = self.get_target(*dir);
+      if ptr.is_nod() {
+        self.heap.set_head(ptr.val(), *dir);
+      }
+    }
+  }
+
+  // Moves new redexes to 'hold', until something depends on them. Redexes between nodes without
+  // ports (such as ERA-NUM) can't block anything, so they're reduced right away.
+  fn hold_rdex(&mut self, book: &Book) -> Result<(), OutOfMemory> {
+    while let Some((a, b)) = self.rdex.pop() {
+      if a.is_nod() {
+        self.hold.insert(a.val(), (a, b));
+      }
+      if b.is_nod() {
+        self.hold.insert(b.val(), (a, b));
+      }
+      if !a.is_nod() && !b.is_nod() {
+        self.interact_lazy(book, a, b)?;
+      }
+    }
+    return Ok(());
+  }
+
+  // Takes the redex held by a node, if any.
+  fn take_held(&mut self, loc: Val) -> Option<(Ptr, Ptr)> {
+    let (a, b) = self.hold.remove(&loc)?;
+    for ptr in [a, b] {
+      if ptr.is_nod() {
+        self.hold.remove(&ptr.val());
+      }
+    }
+    return Some((a, b));
+  }
+
+  // Creates a net that shares this net's heap, to be used by the thread 'tid' of 'tids'.
+  pub fn fork(&self, tid: usize, tids: usize) -> Self {
+    // The last node is never allocated, since LOCK and GONE would be valid pointers to it.
+    let len = self.limit().min(LOCK.val() as usize);
+    Net {
+      rdex: vec![],
+      heap: self.heap.share(),
+      locs: vec![],
+      next: 0,
+      area: ((len * tid / tids).max(1), len * (tid + 1) / tids),
+      tids,
+      cap: self.cap,
+      hold: HashMap::new(),
+      anni: 0,
+      comm: 0,
+      eras: 0,
+      dref: 0,
+      oper: 0,
+      observer: None,
+    }
+  }
+
+  // Reduces all redexes, using many threads sharing the same heap. If an allocation fails, all
+  // threads stop, and the redexes not reduced yet are left on 'rdex'.
+  pub fn reduce_par(&mut self, book: &Book, tids: usize) -> Result<(), OutOfMemory> {
+    // The heap can't grow while shared, so all of it is allocated upfront. Its pages are still only
+    // committed once used.
+    self.heap.grow_to(self.limit());
+    let mut nets: Vec<Net> = (0 .. tids).map(|tid| self.fork(tid, tids)).collect();
+    for (i, redex) in std::mem::take(&mut self.rdex).into_iter().enumerate() {
+      nets[i % tids].rdex.push(redex);
+    }
+    let bags = Bags::new(tids);
+    let done: Vec<Result<(), OutOfMemory>> = std::thread::scope(|s| {
+      let mut threads = vec![];
+      for (tid, net) in nets.iter_mut().enumerate() {
+        let bags = &bags;
+        threads.push(s.spawn(move || net.reduce_shared(book, tid, bags)));
+      }
+      return threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+    });
+    for mut net in nets {
+      self.anni += net.anni;
+      self.comm += net.comm;
+      self.eras += net.eras;
+      self.dref += net.dref;
+      self.oper += net.oper;
+      self.rdex.append(&mut net.rdex);
+    }
+    for bag in bags.bags {
+      self.rdex.append(&mut bag.into_inner().unwrap());
+    }
+    // Other threads allocated all over the heap, so we can't alloc without looking anymore.
+    self.next = self.next.max(self.heap.len);
+    return done.into_iter().collect();
+  }
+
+  // Reduces this thread's redexes, offering half of them on its bag while other threads are idle.
+  // When out of redexes, steals the ones offered by other threads, until all threads are idle.
+  fn reduce_shared(&mut self, book: &Book, tid: usize, bags: &Bags) -> Result<(), OutOfMemory> {
+    loop {
+      while let Some((a, b)) = self.rdex.pop() {
+        if bags.halt.load(Ordering::Relaxed) {
+          self.rdex.push((a, b));
+          return Ok(());
+        }
+        if let Err(err) = self.interact(book, a, b) {
+          self.rdex.push((a, b));
+          bags.halt.store(true, Ordering::Relaxed);
+          return Err(err);
+        }
+        if bags.idle.load(Ordering::Relaxed) > 0 && self.rdex.len() > 1 {
+          bags.offer(tid, &mut self.rdex);
+        }
+      }
+      if bags.steal(tid, &mut self.rdex) {
+        continue;
+      }
+      // Idle: waits until some thread offers redexes, or until all threads are idle.
+      bags.idle.fetch_add(1, Ordering::SeqCst);
+      loop {
+        if bags.idle.load(Ordering::SeqCst) == self.tids || bags.halt.load(Ordering::Relaxed) {
+          return Ok(());
+        }
+        std::thread::yield_now();
+        if bags.any() {
+          bags.idle.fetch_sub(1, Ordering::SeqCst);
+          if bags.steal(tid, &mut self.rdex) {
+            break;
+          }
+          bags.idle.fetch_add(1, Ordering::SeqCst);
+        }
+      }
+    }
+  }
+
+  // Reduces a net to normal form, using many threads sharing the same heap.
+  pub fn normal_par(&mut self, book: &Book, tids: usize) -> Result<(), OutOfMemory> {
+    if tids <= 1 {
+      return self.normal(book);
+    }
+    self.expand(book, ROOT)?;
+    while self.rdex.len() > 0 {
+      self.reduce_par(book, tids)?;
+      self.expand(book, ROOT)?;
+    }
+    self.collapse(ROOT);
+    return Ok(());
+  }
+
+  // Removes the redirections left by atomic links on wires reachable from a port.
+  pub fn collapse(&mut self, dir: Ptr) {
+    let ptr = self.get_target(dir);
+    if ptr.is_var() {
+      let mut trg = ptr;
+      while self.get_target(trg).is_red() {
+        let nxt = self.get_target(trg);
+        self.set_target(trg, NULL);
+        trg = nxt.unredirect();
+      }
+      self.set_target(dir, trg);
+    } else if ptr.is_nod() {
+      self.collapse(Ptr::new(VR1, ptr.val()));
+      self.collapse(Ptr::new(VR2, ptr.val()));
+    }
+  }
+
+  // Checkpoints
+  // -----------
+
+  // Writes the net to a versioned binary checkpoint: the heap size, the used part of the heap and
+  // its labels, 'rdex', 'next' and the rewrite counters. Only for nets that own their heap, between reductions.
+  pub fn save(&self, out: &mut impl Write) -> std::io::Result<()> {
+    write_header(out, NET_MAGIC)?;
+    let mut used = self.heap.len;
+    while used > 0 && self.heap.get(used as Val - 1, P1).is_nil() && self.heap.get(used as Val - 1, P2).is_nil() {
+      used -= 1;
+    }
+    for n in [self.heap.size, used, self.next, self.anni, self.comm, self.eras, self.dref, self.oper] {
+      write_u64(out, n as u64)?;
+    }
+    for i in 0 .. used {
+      write_ptr(out, self.heap.get(i as Val, P1))?;
+      write_ptr(out, self.heap.get(i as Val, P2))?;
+    }
+    write_labs(out, &(0 .. used).map(|i| self.heap.get_lab(i as Val)).collect::<Vec<_>>())?;
+    write_ptrs(out, &self.rdex)?;
+    return Ok(());
+  }
+
+  // Reads a net written by 'save'.
+  pub fn load(inp: &mut impl Read) -> std::io::Result<Net> {
+    read_header(inp, NET_MAGIC)?;
+    let mut nums = [0; 8];
+    for n in &mut nums {
+      *n = read_u64(inp)? as usize;
+    }
+    let [size, used, next, anni, comm, eras, dref, oper] = nums;
+    if used > size || next > size || size > MAX_SIZE {
+      return Err(invalid_data("the heap doesn't fit in its size"));
+    }
+    // Reads the heap before growing it, so a truncated file can't make us allocate all of 'used'.
+    let mut node = vec![];
+    for _ in 0 .. used {
+      node.push((read_ptr(inp)?, read_ptr(inp)?));
+    }
+    let labs = read_labs(inp)?;
+    if labs.len() != used {
+      return Err(invalid_data("the labels don't match the heap"));
+    }
+    let rdex = read_ptrs(inp)?;
+    // Every pointer must be one the runtime could have made: a REF to a def id, an eraser with no
+    // value, and anything else, redirects included, to a location inside the heap.
+    let valid = |ptr: &Ptr| match ptr.tag() {
+      REF => ptr.val() <= DEF_MASK,
+      ERA => ptr.val() == 0,
+      NUM => true,
+      _ => (ptr.val() as usize) < used,
+    };
+    if let Some(ptr) = node.iter().chain(rdex.iter()).flat_map(|(a, b)| [a, b]).find(|ptr| !valid(ptr)) {
+      return Err(invalid_data(&format!("invalid pointer (tag {}, value {})", ptr.tag(), ptr.val())));
+    }
+    let mut net = Net::new(size);
+    net.heap.grow_to(used);
+    for (i, ((p1, p2), lab)) in node.into_iter().zip(labs).enumerate() {
+      net.heap.set(i as Val, P1, p1);
+      net.heap.set(i as Val, P2, p2);
+      net.heap.set_lab(i as Val, lab);
+    }
+    net.rdex = rdex;
+    net.next = next;
+    (net.anni, net.comm, net.eras, net.dref, net.oper) = (anni, comm, eras, dref, oper);
+    return Ok(net);
+  }
+
+}
+
+// Binary checkpoints
+// ------------------
+
+pub const NET_MAGIC: &[u8; 4] = b"HVMN";
+pub const BOOK_MAGIC: &[u8; 4] = b"HVMB";
+
+// Bumped whenever the layout of a checkpoint, or of a Ptr, changes.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+fn invalid_data(msg: &str) -> std::io::Error {
+  return std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+}
+
+fn write_u32(out: &mut impl Write, n: u32) -> std::io::Result<()> {
+  return out.write_all(&n.to_le_bytes());
+}
+
+fn write_u64(out: &mut impl Write, n: u64) -> std::io::Result<()> {
+  return out.write_all(&n.to_le_bytes());
+}
+
+// A pointer, at the width it has in this build.
+fn write_ptr(out: &mut impl Write, ptr: Ptr) -> std::io::Result<()> {
+  return out.write_all(&ptr.data().to_le_bytes());
+}
+
+fn write_ptrs(out: &mut impl Write, ptrs: &[(Ptr, Ptr)]) -> std::io::Result<()> {
+  write_u64(out, ptrs.len() as u64)?;
+  for (a, b) in ptrs {
+    write_ptr(out, *a)?;
+    write_ptr(out, *b)?;
+  }
+  return Ok(());
+}
+
+fn write_labs(out: &mut impl Write, labs: &[Lab]) -> std::io::Result<()> {
+  write_u64(out, labs.len() as u64)?;
+  for lab in labs {
+    out.write_all(&lab.to_le_bytes())?;
+  }
+  return Ok(());
+}
+
+fn read_u32(inp: &mut impl Read) -> std::io::Result<u32> {
+  let mut buf = [0; 4];
+  inp.read_exact(&mut buf)?;
+  return Ok(u32::from_le_bytes(buf));
+}
+
+fn read_u64(inp: &mut impl Read) -> std::io::Result<u64> {
+  let mut buf = [0; 8];
+  inp.read_exact(&mut buf)?;
+  return Ok(u64::from_le_bytes(buf));
+}
+
+fn read_ptr(inp: &mut impl Read) -> std::io::Result<Ptr> {
+  let mut buf = [0; std::mem::size_of::<Val>()];
+  inp.read_exact(&mut buf)?;
+  return Ok(Ptr(Val::from_le_bytes(buf)));
+}
+
+fn read_ptrs(inp: &mut impl Read) -> std::io::Result<Vec<(Ptr, Ptr)>> {
+  let len = read_u64(inp)?;
+  let mut ptrs = vec![];
+  for _ in 0 .. len {
+    ptrs.push((read_ptr(inp)?, read_ptr(inp)?));
+  }
+  return Ok(ptrs);
+}
+
+fn read_labs(inp: &mut impl Read) -> std::io::Result<Vec<Lab>> {
+  let len = read_u64(inp)?;
+  let mut labs = vec![];
+  for _ in 0 .. len {
+    let mut buf = [0; 2];
+    inp.read_exact(&mut buf)?;
+    labs.push(Lab::from_le_bytes(buf));
+  }
+  return Ok(labs);
+}
+
+// The magic, the version, and the width of pointers, which must match the build that loads it.
+fn write_header(out: &mut impl Write, magic: &[u8; 4]) -> std::io::Result<()> {
+  out.write_all(magic)?;
+  write_u32(out, CHECKPOINT_VERSION)?;
+  write_u32(out, Val::BITS)?;
+  return Ok(());
+}
+
+fn read_header(inp: &mut impl Read, magic: &[u8; 4]) -> std::io::Result<()> {
+  let mut buf = [0; 4];
+  inp.read_exact(&mut buf)?;
+  if &buf != magic {
+    return Err(invalid_data("not a checkpoint"));
+  }
+  let version = read_u32(inp)?;
+  if version != CHECKPOINT_VERSION {
+    return Err(invalid_data(&format!("checkpoint version {} isn't supported (expected {})", version, CHECKPOINT_VERSION)));
+  }
+  let bits = read_u32(inp)?;
+  if bits != Val::BITS {
+    return Err(invalid_data(&format!("checkpoint has {}-bit pointers (expected {}-bit)", bits, Val::BITS)));
+  }
+  return Ok(());
 }
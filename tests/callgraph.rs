@@ -0,0 +1,88 @@
+use hvmc::ast::{call_graph, call_graph_sccs, call_graph_to_dot, is_recursive, prune_book, reachable, show_net, Book, Net, Tree};
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+const CODE: &str = "
+  @even = (? (#1 @odd) a a)
+  @odd = (? (#0 @even) a a)
+  @loop = (a b) & @loop ~ (a b)
+  @main = r & @even ~ (#4 r)
+  @unused = (@dead @nope)
+  @dead = *
+";
+
+#[test]
+fn test_call_graph() {
+  let graph = call_graph(&parse_core(CODE));
+  // Refs to undefined defs, like @nope, aren't edges.
+  assert_eq!(graph["unused"].iter().collect::<Vec<_>>(), ["dead"]);
+  assert_eq!(graph["main"].iter().collect::<Vec<_>>(), ["even"]);
+  assert!(graph["dead"].is_empty());
+  let live = reachable(&graph, &["main"]);
+  assert_eq!(live.iter().collect::<Vec<_>>(), ["even", "main", "odd"]);
+  assert!(reachable(&graph, &["nope"]).is_empty());
+}
+
+#[test]
+fn test_call_graph_sccs() {
+  let graph = call_graph(&parse_core(CODE));
+  let sccs = call_graph_sccs(&graph);
+  // Each group comes after the groups it refers to.
+  assert_eq!(sccs, [vec!["dead"], vec!["even", "odd"], vec!["loop"], vec!["main"], vec!["unused"]]);
+  let recursive: Vec<_> = sccs.iter().map(|scc| is_recursive(&graph, scc)).collect();
+  assert_eq!(recursive, [false, true, true, false, false]);
+}
+
+#[test]
+fn test_call_graph_long_chain() {
+  // Deep graphs don't overflow the stack.
+  let mut book = Book::new();
+  for i in 0 .. 100_000 {
+    book.insert(format!("f{}", i), Net { root: Tree::Ref { nam: format!("f{}", i + 1) }, rdex: vec![] });
+  }
+  book.insert("f100000".to_string(), Net { root: Tree::Ref { nam: "f0".to_string() }, rdex: vec![] });
+  let graph = call_graph(&book);
+  let sccs = call_graph_sccs(&graph);
+  assert_eq!(sccs.len(), 1);
+  assert_eq!(sccs[0].len(), 100_001);
+  assert_eq!(reachable(&graph, &["f5"]).len(), 100_001);
+}
+
+#[test]
+fn test_prune() {
+  let book = prune_book(&parse_core(CODE), &["main"]);
+  assert_eq!(book.keys().collect::<Vec<_>>(), ["even", "main", "odd"]);
+  // Pruning doesn't change the result.
+  let (_, net) = normal(book, 1 << 10);
+  let (_, full) = normal(parse_core(CODE), 1 << 10);
+  assert_eq!(show_net(&net), show_net(&full));
+}
+
+#[test]
+fn test_call_graph_dot() {
+  let graph = call_graph(&parse_core(CODE));
+  assert_snapshot!(call_graph_to_dot(&graph, &["main"]), @r###"
+  digraph calls {
+    node [fontname="monospace", shape=box];
+    "dead" [label="@dead", color=grey, fontcolor=grey];
+    subgraph cluster_1 {
+      style=dashed;
+      "even" [label="@even"];
+      "odd" [label="@odd"];
+    }
+    subgraph cluster_2 {
+      style=dashed;
+      "loop" [label="@loop", color=grey, fontcolor=grey];
+    }
+    "main" [label="@main"];
+    "unused" [label="@unused", color=grey, fontcolor=grey];
+    "even" -> "odd";
+    "loop" -> "loop";
+    "main" -> "even";
+    "odd" -> "even";
+    "unused" -> "dead";
+  }
+  "###);
+}
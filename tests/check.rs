@@ -0,0 +1,73 @@
+use hvmc::ast::{check_book, do_parse_book, do_parse_defs, show_net, Net, Tree};
+use hvmc::run;
+use loaders::*;
+
+mod loaders;
+
+fn check(code: &str) -> Vec<String> {
+  check_book(&parse_core(code)).iter().map(|diag| diag.to_string()).collect()
+}
+
+#[test]
+fn test_check_ok() {
+  assert!(check("@id = (a a)\n@main = a & @id ~ (#1 a)").is_empty());
+  let path = format!("{}/examples/church.hvmc", env!("CARGO_MANIFEST_DIR"));
+  assert!(check(&std::fs::read_to_string(path).unwrap()).is_empty());
+}
+
+#[test]
+fn test_check_vars() {
+  assert_eq!(check("@main = (a (b b)) & (c a) ~ (a c)"), [
+    "@main: variable 'a' occurs 3 times (instead of twice)",
+  ]);
+  assert_eq!(check("@main = (a b)"), [
+    "@main: variable 'a' occurs 1 time (instead of twice)",
+    "@main: variable 'b' occurs 1 time (instead of twice)",
+  ]);
+}
+
+#[test]
+fn test_check_var_redex() {
+  assert_eq!(check("@main = a & a ~ *"), ["@main: variable 'a' is on an active pair"]);
+}
+
+#[test]
+fn test_check_refs() {
+  assert_eq!(check("@main = a & @foo ~ (#1 a)"), ["@main: @foo isn't defined"]);
+  // Names of any length are fine.
+  assert!(check("@Main.List.sum.go = (a a)\n@main = a & @Main.List.sum.go ~ (#1 a)").is_empty());
+}
+
+#[test]
+fn test_check_encoding() {
+  let big: run::Val = 1 << (run::NUM_BITS + run::OPR_BITS);
+  let mut book = parse_core("@main = *");
+  book.insert("big".to_string(), Net { root: Tree::Num { val: big }, rdex: vec![] });
+  let diags: Vec<_> = check_book(&book).iter().map(|diag| diag.to_string()).collect();
+  assert_eq!(diags, [format!("@big: number {} doesn't fit in {} bits", big, run::NUM_BITS + run::OPR_BITS)]);
+  // Floats use the operation bits too.
+  assert!(check("@main = #-1.5").is_empty());
+}
+
+#[test]
+fn test_check_redex_rules() {
+  // Pairs the runtime has no rule for, which would panic when reduced.
+  assert_eq!(check("@f = *\n@main = * & @f ~ #1"), [
+    "@main: a ref and a number are on an active pair, which has no rule",
+  ]);
+  assert_eq!(check("@main = (a b) & <a c> ~ <b c>"), [
+    "@main: an operation and an operation are on an active pair, which has no rule",
+  ]);
+  // Everything else has one.
+  assert!(check("@f = *\n@main = a & @f ~ * & #1 ~ (a b) & {3 b c} ~ ? (#1 c) *").is_empty());
+}
+
+#[test]
+fn test_check_duplicates() {
+  let code = "@id = (a a)\n@main = a & @id ~ (#1 a)\n@id = *\n@id = (b b)";
+  let (defs, _) = do_parse_defs(code).unwrap();
+  let diags: Vec<_> = check_book(defs.iter().map(|(name, net)| (name, net))).iter().map(|diag| diag.to_string()).collect();
+  assert_eq!(diags, ["@id: defined 3 times (the last definition is used)"]);
+  // Everything else loads the last definition.
+  assert_eq!(show_net(&do_parse_book(code).unwrap()["id"]), "(b b)");
+}
@@ -0,0 +1,143 @@
+use hvmc::ast::{book_to_runtime, net_from_runtime, show_net};
+use hvmc::run;
+use loaders::*;
+
+mod loaders;
+
+fn save(net: &run::Net, book: &run::Book) -> Vec<u8> {
+  let mut buf = vec![];
+  net.save(&mut buf).unwrap();
+  book.save(&mut buf).unwrap();
+  buf
+}
+
+#[test]
+fn test_checkpoint_resumes() {
+  // Saves halfway, and continues from the loaded net and book.
+  let book = sum_rec();
+  let mut net = run::Net::new(1 << 16);
+  net.boot(book.id("main").unwrap());
+  for _ in 0 .. 20 {
+    net.reduce_with_fuel(&book, 100).unwrap();
+  }
+  let buf = save(&net, &book);
+  let mut inp = &buf[..];
+  let mut net = run::Net::load(&mut inp).unwrap();
+  let book = run::Book::load(&mut inp).unwrap();
+  assert!(inp.is_empty());
+  assert_eq!(net.rewrites(), 2000);
+  net.normal(&book).unwrap();
+
+  let rbook = sum_rec();
+  let mut rnet = run::Net::new(1 << 16);
+  rnet.boot(rbook.id("main").unwrap());
+  rnet.normal(&rbook).unwrap();
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), show_net(&net_from_runtime(&rnet, &rbook)));
+  assert_eq!(net.rewrites(), rnet.rewrites());
+}
+
+#[test]
+fn test_checkpoint_round_trip() {
+  let book = sum_rec();
+  let mut net = run::Net::new(1 << 16);
+  net.boot(book.id("main").unwrap());
+  net.reduce_with_fuel(&book, 500).unwrap();
+  let buf = save(&net, &book);
+  let mut inp = &buf[..];
+  let loaded = run::Net::load(&mut inp).unwrap();
+  assert_eq!(loaded.heap.size, net.heap.size);
+  assert_eq!(loaded.next, net.next);
+  assert_eq!(loaded.rdex, net.rdex);
+  assert_eq!(show_net(&net_from_runtime(&loaded, &book)), show_net(&net_from_runtime(&net, &book)));
+  // Saving again gives the same bytes.
+  assert_eq!(save(&loaded, &run::Book::load(&mut inp).unwrap()), buf);
+}
+
+#[test]
+fn test_checkpoint_version() {
+  let mut buf = vec![];
+  run::Net::new(1 << 10).save(&mut buf).unwrap();
+  buf[4] += 1;
+  let err = run::Net::load(&mut &buf[..]).err().unwrap();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+  assert!(err.to_string().contains("version"));
+  let err = run::Book::load(&mut &buf[..]).err().unwrap();
+  assert_eq!(err.to_string(), "not a checkpoint");
+  assert!(run::Net::load(&mut &buf[.. 10]).is_err());
+}
+
+#[test]
+fn test_checkpoint_pointer_width() {
+  // A checkpoint can only be loaded by a build with the same pointer width.
+  let mut buf = vec![];
+  run::Net::new(1 << 10).save(&mut buf).unwrap();
+  assert_eq!(buf[8 .. 12], run::Val::BITS.to_le_bytes());
+  buf[8 .. 12].copy_from_slice(&(96u32).to_le_bytes());
+  let err = run::Net::load(&mut &buf[..]).err().unwrap();
+  assert_eq!(err.to_string(), format!("checkpoint has 96-bit pointers (expected {}-bit)", run::Val::BITS));
+}
+
+#[test]
+fn test_checkpoint_labels() {
+  // Labels on the heap are saved with the net and its book.
+  let code = "
+    @k = {1000 #1 #2}
+    @main = (a b) & @k ~ {1000 a b}
+  ";
+  let book = book_to_runtime(&parse_core(code), run::call_native());
+  let mut net = run::Net::new(1 << 10);
+  net.boot(book.id("main").unwrap());
+  net.reduce_with_fuel(&book, 1).unwrap();
+  assert_eq!(net.rewrites(), 1);
+  let buf = save(&net, &book);
+  let mut inp = &buf[..];
+  let mut net = run::Net::load(&mut inp).unwrap();
+  let book = run::Book::load(&mut inp).unwrap();
+  assert_eq!(book.defs[book.id("k").unwrap() as usize].labs, vec![0, 1000]);
+  net.normal(&book).unwrap();
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), "(#1 #2)");
+}
+
+#[test]
+fn test_checkpoint_corrupted() {
+  // A pointer out of the heap is an error, not a crash when reducing.
+  let book = sum_rec();
+  let mut net = run::Net::new(1 << 10);
+  net.boot(book.id("main").unwrap());
+  net.rdex.push((run::Ptr::new(run::VR1, 1000), run::ERAS));
+  let mut buf = vec![];
+  net.save(&mut buf).unwrap();
+  let err = run::Net::load(&mut &buf[..]).err().unwrap();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+  assert_eq!(err.to_string(), "invalid pointer (tag 0, value 1000)");
+  // So is a def name longer than the file.
+  let mut buf = vec![];
+  book.save(&mut buf).unwrap();
+  let last = book.names.last().unwrap();
+  let at = buf.len() - last.len() - 8;
+  assert_eq!(buf[at .. at + 8], (last.len() as u64).to_le_bytes());
+  buf[at .. at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+  let err = run::Book::load(&mut &buf[..]).err().unwrap();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_checkpoint_tampered() {
+  // Overwrites the first pointer of the heap, after the header and the 8 counters, of a saved net.
+  let tamper = |ptr: run::Ptr| {
+    let mut net = run::Net::new(1 << 10);
+    net.boot(0);
+    let mut buf = vec![];
+    net.save(&mut buf).unwrap();
+    buf[76 .. 76 + std::mem::size_of::<run::Val>()].copy_from_slice(&ptr.data().to_le_bytes());
+    run::Net::load(&mut &buf[..]).map(|_| ()).map_err(|err| err.to_string())
+  };
+  assert_eq!(tamper(run::Ptr::new(run::RD1, 1000)), Err("invalid pointer (tag 2, value 1000)".to_string()));
+  assert_eq!(tamper(run::Ptr::new(run::CT5, 1000)), Err("invalid pointer (tag 15, value 1000)".to_string()));
+  assert_eq!(tamper(run::Ptr::new(run::ERA, 1)), Err("invalid pointer (tag 5, value 1)".to_string()));
+  assert_eq!(tamper(run::Ptr::new(run::REF, run::DEF_MASK + 1)), Err(format!("invalid pointer (tag 4, value {})", run::DEF_MASK + 1)));
+  // Pointers the runtime could have made are fine.
+  assert_eq!(tamper(run::Ptr::new(run::NUM, 1000)), Ok(()));
+  assert_eq!(tamper(run::Ptr::new(run::RD2, 0)), Ok(()));
+  assert_eq!(tamper(run::Ptr::new(run::REF, run::DEF_MASK)), Ok(()));
+}
@@ -0,0 +1,74 @@
+use hvmc::ast::{canonical_net, dedup_book, show_book, show_net};
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+#[test]
+fn test_canonical_net() {
+  // Variables are named in order, and refs to the def itself lose their name.
+  let net = &parse_core("@f = (q (r q)) & @f ~ (r @g)")["f"];
+  assert_snapshot!(show_net(&canonical_net("f", net)), @r###"
+  (a (b a))
+  & @ ~ (b @g)
+  "###);
+}
+
+#[test]
+fn test_dedup() {
+  let book = parse_core("
+    @a = (x x)
+    @b = (y y)
+    @c = {2 x x}
+    @d = {3 x x}
+    @main = ((@a @b) (@c @d))
+  ");
+  assert_snapshot!(show_book(&dedup_book(&book, &["main"])), @r###"
+  @a = (x x)
+  @c = {2 x x}
+  @d = {3 x x}
+  @main = ((@a @a) (@c @d))
+  "###);
+}
+
+#[test]
+fn test_dedup_repeats() {
+  // Once @b is merged into @a, @fb is the same as @fa.
+  let book = parse_core("
+    @a = (x x)
+    @b = (y y)
+    @fa = (@a *)
+    @fb = (@b *)
+    @main = (@fa @fb)
+  ");
+  assert_snapshot!(show_book(&dedup_book(&book, &["main"])), @r###"
+  @a = (x x)
+  @fa = (@a *)
+  @main = (@fa @fa)
+  "###);
+}
+
+#[test]
+fn test_dedup_recursive() {
+  let book = parse_core("
+    @la = (a b) & @la ~ (a b)
+    @lb = (c d) & @lb ~ (c d)
+    @main = (@la @lb)
+  ");
+  assert_snapshot!(show_book(&dedup_book(&book, &["main"])), @r###"
+  @la = (a b)
+  & @la ~ (a b)
+  @main = (@la @la)
+  "###);
+}
+
+#[test]
+fn test_dedup_roots() {
+  // A root is kept, and the defs like it are merged into it.
+  let book = parse_core("@id = (y y)\n@main = (x x)\n@other = (@id *)");
+  assert_snapshot!(show_book(&dedup_book(&book, &["main"])), @r###"
+  @main = (x x)
+  @other = (@main *)
+  "###);
+  assert_eq!(dedup_book(&book, &["main", "id"]), book);
+}
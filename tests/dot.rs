@@ -0,0 +1,59 @@
+use hvmc::ast::{do_parse_net, net_to_dot};
+use insta::assert_snapshot;
+
+#[test]
+fn test_dot_kinds() {
+  let net = do_parse_net("(a {3 <#1 b> @foo}) & (* a) ~ [? (#0 c) c b]").unwrap();
+  assert_snapshot!(net_to_dot(&net), @r###"
+  graph net {
+    node [fontname="monospace", margin=0];
+    root [shape=plaintext, label="ROOT"];
+    n0 [shape=triangle, label="CT0"];
+    root -- n0:n [];
+    n1 [shape=triangle, label="CT3"];
+    n0:se -- n1:n [];
+    n2 [shape=triangle, style=filled, fillcolor=lightblue, label="OP2"];
+    n1:sw -- n2:n [];
+    n3 [shape=ellipse, label="#1"];
+    n2:sw -- n3 [];
+    n4 [shape=box, label="@foo"];
+    n1:se -- n4 [];
+    rdex0 [shape=point, color=red, width=0.15];
+    n5 [shape=triangle, label="CT0"];
+    rdex0 -- n5:n [color=red, penwidth=2];
+    n6 [shape=circle, label="*"];
+    n5:sw -- n6 [];
+    n0:sw -- n5:se [style=dashed];
+    n7 [shape=triangle, label="CT1"];
+    rdex0 -- n7:n [color=red, penwidth=2];
+    n8 [shape=triangle, style=filled, fillcolor=lightyellow, label="MAT"];
+    n7:sw -- n8:n [];
+    n9 [shape=triangle, label="CT0"];
+    n8:sw -- n9:n [];
+    n10 [shape=ellipse, label="#0"];
+    n9:sw -- n10 [];
+    n9:se -- n8:se [style=dashed];
+    n2:se -- n7:se [style=dashed];
+  }
+  "###);
+}
+
+#[test]
+fn test_dot_free_vars() {
+  // A variable that occurs once is drawn as a free wire.
+  let net = do_parse_net("(a b) & a ~ *").unwrap();
+  assert_snapshot!(net_to_dot(&net), @r###"
+  graph net {
+    node [fontname="monospace", margin=0];
+    root [shape=plaintext, label="ROOT"];
+    n0 [shape=triangle, label="CT0"];
+    root -- n0:n [];
+    rdex0 [shape=point, color=red, width=0.15];
+    n0:sw -- rdex0 [style=dashed];
+    n1 [shape=circle, label="*"];
+    rdex0 -- n1 [color=red, penwidth=2];
+    "free_b" [shape=plaintext, label="b"];
+    n0:se -- "free_b" [style=dashed];
+  }
+  "###);
+}
@@ -0,0 +1,94 @@
+use hvmc::ast::{book_to_runtime, net_from_runtime, show_net, Book};
+use hvmc::run::{self, Status};
+use loaders::*;
+
+mod loaders;
+
+// Checks that reducing with small amounts of fuel at a time reaches the same normal form, with the
+// same rewrites, as reducing all at once.
+fn assert_same_normal(book: Book, size: usize, fuel: usize) {
+  let book = book_to_runtime(&book, run::call_native());
+  let mut net = run::Net::new(size);
+  net.boot(book.id("main").unwrap());
+  net.normal(&book).unwrap();
+
+  let mut fuel_net = run::Net::new(size);
+  fuel_net.boot(book.id("main").unwrap());
+  let mut calls = 0;
+  while fuel_net.reduce_with_fuel(&book, fuel).unwrap() == Status::OutOfFuel {
+    calls += 1;
+  }
+  assert!(calls > 1);
+  assert_eq!(show_net(&net_from_runtime(&fuel_net, &book)), show_net(&net_from_runtime(&net, &book)));
+  assert_eq!(fuel_net.rewrites(), net.rewrites());
+}
+
+#[test]
+fn test_fuel_church_exp() {
+  assert_same_normal(load_core("benches/programs/church/church_exp.hvmc", &[]), 1 << 16, 10);
+}
+
+#[test]
+fn test_fuel_sum_rec() {
+  assert_same_normal(load_core("examples/sum_rec.hvmc", &[("#24", "#10")]), 1 << 16, 7);
+}
+
+#[test]
+fn test_fuel_dec_bits_tree() {
+  assert_same_normal(load_core("benches/programs/binary-counter/dec_bits_tree.hvmc", &[]), 1 << 16, 1000);
+}
+
+#[test]
+fn test_fuel_loop() {
+  let book = parse_core("@loop = (x r) & @loop ~ (x r)  @main = r & @loop ~ (#0 r)");
+  let book = book_to_runtime(&book, run::call_native());
+  let mut net = run::Net::new(1 << 10);
+  net.boot(book.id("main").unwrap());
+  for i in 1 ..= 100 {
+    assert_eq!(net.reduce_with_fuel(&book, 50), Ok(Status::OutOfFuel));
+    assert_eq!(net.rewrites(), i * 50);
+  }
+}
+
+#[test]
+fn test_fuel_normal() {
+  let book = parse_core("@main = root & (x x) ~ [* root]");
+  let book = book_to_runtime(&book, run::call_native());
+  let mut net = run::Net::new(1 << 10);
+  net.boot(book.id("main").unwrap());
+  assert_eq!(net.reduce_with_fuel(&book, 100), Ok(Status::Normal));
+  assert_eq!(net.reduce_with_fuel(&book, 100), Ok(Status::Normal));
+  assert_eq!(net.rewrites(), 5);
+}
+
+#[test]
+fn test_step_sum_rec() {
+  // Reducing one redex at a time reaches the same normal form, with the same rewrites.
+  let book = book_to_runtime(&load_core("examples/sum_rec.hvmc", &[("#24", "#6")]), run::call_native());
+  let mut net = run::Net::new(1 << 12);
+  net.boot(book.id("main").unwrap());
+  net.normal(&book).unwrap();
+
+  let mut step_net = run::Net::new(1 << 12);
+  step_net.boot(book.id("main").unwrap());
+  let mut steps = 0;
+  while step_net.step(&book).unwrap().is_some() {
+    steps += 1;
+  }
+  assert!(steps > 1);
+  assert_eq!(show_net(&net_from_runtime(&step_net, &book)), show_net(&net_from_runtime(&net, &book)));
+  assert_eq!(step_net.rewrites(), net.rewrites());
+  assert_eq!(step_net.step(&book), Ok(None));
+}
+
+#[test]
+fn test_step_order() {
+  let book = parse_core("@main = a & (b b) ~ (c a) & (d d) ~ (#1 c)");
+  let book = book_to_runtime(&book, run::call_native());
+  let mut net = run::Net::new(1 << 10);
+  net.boot(book.id("main").unwrap());
+  net.expand(&book, run::ROOT).unwrap();
+  let rdex = net.rdex.clone();
+  assert_eq!(net.step(&book), Ok(Some(rdex[0])));
+  assert_eq!(net.rdex[0], rdex[1]);
+}
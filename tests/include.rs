@@ -0,0 +1,74 @@
+use hvmc::ast::{do_parse_book, do_parse_file, link_book, load_book, prefix_book, show_book, show_net, Include, LoadError};
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+fn path(file: &str) -> String {
+  format!("{}/tests/include/{}", env!("CARGO_MANIFEST_DIR"), file)
+}
+
+#[test]
+fn test_parse_includes() {
+  let code = "@include \"lib/list.hvmc\" as List\n@include \"nat.hvmc\"\n@main = *";
+  let (book, includes) = do_parse_file(code).unwrap();
+  assert_eq!(book.keys().collect::<Vec<_>>(), ["main"]);
+  assert_eq!(includes, [
+    Include { path: "lib/list.hvmc".to_string(), prefix: Some("List".to_string()), span: 0 .. 32 },
+    Include { path: "nat.hvmc".to_string(), prefix: None, span: 33 .. 52 },
+  ]);
+  // A def can still be named 'include'.
+  assert!(do_parse_file("@include = *").unwrap().0.contains_key("include"));
+  let err = do_parse_file("@include \"nat.hvmc\" with Nat").unwrap_err();
+  assert_eq!(err.msg, "Expected 'as' or '@' or end of input");
+  let err = do_parse_file("@include \"nat.hvmc\n").unwrap_err();
+  assert_eq!(err.msg, "Expected '\"', found '\n'");
+  // Books parsed from a string have nowhere to find includes from.
+  let err = do_parse_book(code).unwrap_err();
+  assert_eq!(err.msg, "Includes can only be loaded from a file");
+  assert_eq!(err.span, 0 .. 32);
+}
+
+#[test]
+fn test_load_includes() {
+  let book = load_book(&path("main.hvmc")).unwrap();
+  assert_eq!(book.keys().collect::<Vec<_>>(), ["List.cons", "List.nil", "List.sum", "List.sum.cons", "double", "main"]);
+  assert_eq!(show_net(&book["List.sum"]), "((@List.sum.cons (#0 r)) r)");
+  let (_, net) = normal(book, 1 << 10);
+  assert_snapshot!(show_net(&net), @"#6");
+}
+
+#[test]
+fn test_include_twice() {
+  // The same defs can be included more than once, as when two libraries include a third.
+  let (_, net) = normal(load_book(&path("twice.hvmc")).unwrap(), 1 << 10);
+  assert_snapshot!(show_net(&net), @"#0");
+}
+
+#[test]
+fn test_include_errors() {
+  let err = load_book(&path("conflict.hvmc")).unwrap_err();
+  assert!(matches!(&err, LoadError::Conflict { include, names, .. } if include == "list.hvmc" && names == &["nil", "sum"]));
+  assert_eq!(err.to_string(), format!("{}: list.hvmc defines @nil, @sum differently", path("conflict.hvmc")));
+  let err = load_book(&path("cycle.hvmc")).unwrap_err();
+  assert!(matches!(err, LoadError::Cycle { .. }));
+  assert!(err.to_string().ends_with("cycle.hvmc: the file includes itself"));
+  let err = load_book(&path("missing.hvmc")).unwrap_err();
+  assert!(matches!(err, LoadError::Io { .. }));
+}
+
+#[test]
+fn test_link() {
+  // Refs to names the library doesn't define are left for the book it is linked to.
+  let lib = prefix_book(&parse_core("@map = (@f @map.go)\n@map.go = *"), "Lib");
+  assert_snapshot!(show_book(&lib), @r###"
+  @Lib.map = (@f @Lib.map.go)
+  @Lib.map.go = *
+  "###);
+  let mut book = parse_core("@f = *\n@main = @Lib.map");
+  link_book(&mut book, lib.clone()).unwrap();
+  assert_eq!(book.len(), 4);
+  link_book(&mut book, lib).unwrap();
+  assert_eq!(link_book(&mut book, parse_core("@f = (a a)\n@g = *")), Err(vec!["f".to_string()]));
+  assert!(!book.contains_key("g"));
+}
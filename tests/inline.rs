@@ -0,0 +1,74 @@
+use hvmc::ast::{inline_book, net_size, show_book, show_net};
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+#[test]
+fn test_inline_aliases() {
+  let book = parse_core("
+    @a = @b
+    @b = @c
+    @c = (x x)
+    @k = #7
+    @main = r & @a ~ (@k r)
+  ");
+  // Aliases and constants are inlined regardless of the size, and the defs only they used go away.
+  assert_snapshot!(show_book(&inline_book(&book, &["main"], 0)), @r###"
+  @c = (x x)
+  @main = r
+  & @c ~ (#7 r)
+  "###);
+}
+
+#[test]
+fn test_inline_calls() {
+  let code = "
+    @id = (x x)
+    @twice = ((a b) (c d)) & (b c) ~ (a d)
+    @main = a & @twice ~ (@id (#1 a))
+  ";
+  let book = inline_book(&parse_core(code), &["main"], 32);
+  // The inlined variables don't clash with the caller's.
+  assert_snapshot!(show_net(&book["main"]), @r###"
+  a
+  & (b c) ~ (d e)
+  & ((d b) (c e)) ~ (@id (#1 a))
+  "###);
+  // @id isn't on an active pair, so it's kept.
+  assert!(book.contains_key("id"));
+  assert!(!book.contains_key("twice"));
+  let (rnet, net) = normal(parse_core(code), 16);
+  let (inlined_rnet, inlined_net) = normal(book, 16);
+  assert_eq!(show_net(&inlined_net), show_net(&net));
+  assert_eq!(inlined_rnet.dref, rnet.dref - 1);
+}
+
+#[test]
+fn test_inline_keeps() {
+  let book = parse_core("
+    @loop = (a b) & @loop ~ (a b)
+    @big = ((a a) (b b))
+    @main = (@big r) & @loop ~ (* r)
+  ");
+  // Recursive defs, defs bigger than the limit, and refs that could be erased aren't inlined.
+  assert_eq!(net_size(&book["big"]), 3);
+  assert_eq!(inline_book(&book, &["main"], 2), book);
+  let book = parse_core("@main = @k\n@k = *\n@other = (@main *)");
+  // The roots are never inlined, but they are inlined into.
+  assert_snapshot!(show_book(&inline_book(&book, &["main"], 32)), @r###"
+  @main = *
+  @other = (@main *)
+  "###);
+}
+
+#[test]
+fn test_inline_church() {
+  // Inlining doesn't change the result.
+  let path = format!("{}/examples/church.hvmc", env!("CARGO_MANIFEST_DIR"));
+  let book = parse_core(&std::fs::read_to_string(path).unwrap());
+  let (rnet, net) = normal(book.clone(), 1 << 12);
+  let (inlined_rnet, inlined_net) = normal(inline_book(&book, &["main"], 32), 1 << 12);
+  assert_eq!(show_net(&inlined_net), show_net(&net));
+  assert!(inlined_rnet.dref <= rnet.dref);
+}
@@ -0,0 +1,101 @@
+use hvmc::ast::{self, book_to_runtime, net_from_runtime, show_net};
+use hvmc::run;
+use insta::{assert_debug_snapshot, assert_snapshot};
+use loaders::*;
+
+mod loaders;
+
+#[test]
+fn test_big_label_anni() {
+  let net = parse_core("@main = (a b) & {1000 a b} ~ {1000 #1 #2}");
+  let (rnet, net) = normal(net, 16);
+  assert_snapshot!(show_net(&net), @"(#1 #2)");
+  assert_debug_snapshot!(rnet.rewrites(), @"2");
+}
+
+#[test]
+fn test_big_label_comm() {
+  // Labels on the heap are copied to the nodes made by a commutation.
+  let net = parse_core("@main = (a b) & {1000 a b} ~ {1001 #1 #2}");
+  let (rnet, net) = normal(net, 16);
+  assert_snapshot!(show_net(&net), @"({1001 #1 #2} {1001 #1 #2})");
+  assert_debug_snapshot!(rnet.rewrites(), @"4");
+}
+
+#[test]
+fn test_tag_and_heap_labels() {
+  // Labels 4 and 5 are on the tag and on the heap, and still commute.
+  let net = parse_core("@main = (a b) & {4 a b} ~ {5 #1 #2}");
+  let (_, net) = normal(net, 16);
+  assert_snapshot!(show_net(&net), @"({5 #1 #2} {5 #1 #2})");
+  let net = parse_core("@main = (a b) & {5 a b} ~ {5 #1 #2}");
+  let (_, net) = normal(net, 16);
+  assert_snapshot!(show_net(&net), @"(#1 #2)");
+}
+
+#[test]
+fn test_big_label_call() {
+  // Expanding a REF loads the labels of its nodes.
+  let book = "
+    @k = {60000 #1 #2}
+    @main = (a b) & @k ~ {60000 a b}
+  ";
+  let (rnet, net) = normal(parse_core(book), 16);
+  assert_snapshot!(show_net(&net), @"(#1 #2)");
+  assert_debug_snapshot!(rnet.rewrites(), @"3");
+  let book = "
+    @k = {60000 #1 #2}
+    @main = (a b) & @k ~ {60001 a b}
+  ";
+  let (_, net) = normal(parse_core(book), 16);
+  assert_snapshot!(show_net(&net), @"({60000 #1 #2} {60000 #1 #2})");
+}
+
+#[test]
+fn test_big_label_kind() {
+  let book = book_to_runtime(&parse_core("@main = * & {1000 * *} ~ {1001 * *}"), run::call_native());
+  let mut net = run::Net::new(16);
+  net.boot(book.id("main").unwrap());
+  net.expand(&book, run::ROOT).unwrap();
+  let (a, b) = net.rdex[0];
+  assert_eq!(run::Kind::of(a, b), run::Kind::Anni);
+  assert_eq!(net.heap.kind(a, b), run::Kind::Comm);
+}
+
+#[test]
+fn test_big_label_def() {
+  // Only CT5 nodes keep a label on their def.
+  let book = book_to_runtime(&parse_core("@a = {1000 * *}\n@b = {4 * *}"), run::call_native());
+  assert_eq!(book.defs[book.id("a").unwrap() as usize].labs, vec![0, 1000]);
+  assert!(book.defs[book.id("b").unwrap() as usize].labs.is_empty());
+  let net = run::Net::from_def(book.defs[book.id("a").unwrap() as usize].clone());
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), "{1000 * *}");
+}
+
+#[test]
+fn test_label_too_large() {
+  let err = ast::do_parse_tree("{65536 * *}").unwrap_err().msg;
+  assert_eq!(err, "Label 65536 doesn't fit in 16 bits");
+}
+
+#[test]
+fn test_big_labels_church() {
+  // Moving labels to the heap doesn't change the result.
+  let path = format!("{}/examples/church.hvmc", env!("CARGO_MANIFEST_DIR"));
+  let code = std::fs::read_to_string(path).unwrap();
+  let (rnet, net) = normal(parse_core(&code), 1 << 12);
+  let big = replace_template(code, &[("{2 ", "{1002 "), ("{3 ", "{1003 ")]);
+  let (big_rnet, big_net) = normal(parse_core(&big), 1 << 12);
+  assert_eq!(show_net(&big_net), show_net(&net).replace("{2 ", "{1002 ").replace("{3 ", "{1003 "));
+  assert_eq!(big_rnet.rewrites(), rnet.rewrites());
+}
+
+#[test]
+#[cfg(not(feature = "ptr64"))]
+fn test_cuda_book_labels() {
+  // The CUDA runtime has label 5 on the CT5 tag, as it was before labels were stored on the heap.
+  let book = run_cli("gen-cuda-book", "@main = (a b) & {5 a b} ~ {5 #1 #2}", &[]).unwrap();
+  assert!(book.contains("0x0000002F, 0x0000003F,"));
+  let err = run_cli("gen-cuda-book", "@main = (a b) & {6 a b} ~ {6 #1 #2}", &[]).unwrap_err();
+  assert_eq!(err, "@main has a label over 5, which the CUDA runtime doesn't support\n");
+}
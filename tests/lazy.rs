@@ -0,0 +1,86 @@
+use hvmc::ast::{book_to_runtime, net_from_runtime, show_net, Book};
+use hvmc::run;
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+fn normal_lazy(book: Book, size: usize) -> (run::Net, String) {
+  let book = book_to_runtime(&book, run::call_native());
+  let mut rnet = run::Net::new(size);
+  rnet.boot(book.id("main").unwrap());
+  rnet.normal_lazy(&book).unwrap();
+  let net = show_net(&net_from_runtime(&rnet, &book));
+  (rnet, net)
+}
+
+// Checks that the lazy mode reaches the same normal form as the eager one.
+fn assert_same_normal(book: Book, size: usize) {
+  let (_, net) = normal(book.clone(), size);
+  let (rnet, lazy_net) = normal_lazy(book, size);
+  assert_eq!(lazy_net, show_net(&net));
+  assert!(rnet.rdex.is_empty());
+}
+
+#[test]
+fn test_lazy_commutation() {
+  assert_same_normal(parse_core("@main = root & (x x) ~ [* root]"), 1 << 10);
+}
+
+#[test]
+fn test_lazy_church() {
+  assert_same_normal(load_core("examples/church.hvmc", &[]), 1 << 12);
+}
+
+#[test]
+fn test_lazy_num_match() {
+  assert_same_normal(load_core("examples/num_match.hvmc", &[]), 1 << 10);
+}
+
+#[test]
+fn test_lazy_sum_rec() {
+  assert_same_normal(load_core("examples/sum_rec.hvmc", &[("#24", "#10")]), 1 << 16);
+}
+
+#[test]
+fn test_lazy_dec_bits_tree() {
+  assert_same_normal(load_core("benches/programs/binary-counter/dec_bits_tree.hvmc", &[]), 1 << 16);
+}
+
+#[test]
+fn test_lazy_unused_loop() {
+  // Eagerly, `@loop` expands forever. Lazily, nothing depends on it, so it is left unreduced.
+  let book = parse_core(
+    "
+    @K    = (a (* a))
+    @loop = (x r) & @loop ~ (x r)
+    @main = r & @K ~ (#42 (l r)) & @loop ~ (#0 l)
+  ",
+  );
+  let (_, net) = normal_lazy(book, 1 << 10);
+  assert_snapshot!(net, @r###"
+  #42
+  & @loop ~ (#0 *)
+  "###);
+}
+
+#[test]
+fn test_lazy_sum_non_supercombinator() {
+  // The recursive call isn't inside a match branch, so it is only stopped by laziness. The last
+  // call is never needed, and is left unreduced.
+  let book = parse_core(
+    "
+    @sum  = ({2 ? (#0 (* o)) r {2 ? (#0 (p p)) m n}} r)
+      & @sum ~ (m s)
+      & #1 ~ <n <s o>>
+    @main = r & @sum ~ (#10 r)
+  ",
+  );
+  let (_, net) = normal_lazy(book, 1 << 12);
+  assert_snapshot!(net, @r###"
+  #55
+  & {2 ? (#0 (a a)) b c} ~ #0
+  & @sum ~ (b d)
+  & #1 ~ <c <d *>>
+  "###);
+}
@@ -2,7 +2,7 @@
 
 use hvm_lang::term::{parser, DefId, Book as DefinitionBook};
 use hvmc::{ast::*, run};
-use std::{collections::HashMap, fs};
+use std::{fs, process::Command, sync::atomic::{AtomicUsize, Ordering}};
 
 pub fn load_file(file: &str) -> String {
   let path = format!("{}/tests/programs/{}", env!("CARGO_MANIFEST_DIR"), file);
@@ -11,7 +11,7 @@ pub fn load_file(file: &str) -> String {
 
 // Parses code and generate Book from hvm-core syntax
 pub fn parse_core(code: &str) -> Book {
-  do_parse_book(code)
+  do_parse_book(code).unwrap()
 }
 
 // Parses code and generate DefinitionBook from hvm-lang syntax
@@ -33,14 +33,41 @@ pub fn replace_template(mut code: String, map: &[(&str, &str)]) -> String {
   code
 }
 
-pub fn hvm_lang_readback(net: &Net, book: &DefinitionBook, id_map: HashMap<run::Val, DefId>) -> (String, bool) {
+// Loads a file relative to the crate's root, and parses it as hvm-core after replacing the map
+pub fn load_core(file: &str, map: &[(&str, &str)]) -> Book {
+  let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file);
+  parse_core(&replace_template(fs::read_to_string(path).unwrap(), map))
+}
+
+// The sum_rec example, with a smaller depth, compiled to the runtime
+pub fn sum_rec() -> run::Book {
+  book_to_runtime(&load_core("examples/sum_rec.hvmc", &[("#24", "#10")]), run::call_native())
+}
+
+// Runs the hvmc binary on some hvm-core code, which is written to a temporary file, returning its
+// output, or its errors if it fails
+pub fn run_cli(cmd: &str, code: &str, args: &[&str]) -> Result<String, String> {
+  static FILES: AtomicUsize = AtomicUsize::new(0);
+  let file = format!("hvmc-test-{}-{}.hvmc", std::process::id(), FILES.fetch_add(1, Ordering::Relaxed));
+  let path = std::env::temp_dir().join(file);
+  fs::write(&path, code).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_hvmc")).arg(cmd).arg(&path).args(args).output().unwrap();
+  fs::remove_file(&path).unwrap();
+  if output.status.success() {
+    Ok(String::from_utf8(output.stdout).unwrap())
+  } else {
+    Err(String::from_utf8(output.stderr).unwrap())
+  }
+}
+
+pub fn hvm_lang_readback(net: &Net, book: &DefinitionBook, id_map: std::collections::HashMap<run::Val, DefId>) -> (String, bool) {
   let net = hvm_lang::net::hvmc_to_net::hvmc_to_net(net, &|val| id_map[&val]);
   let (res_term, valid_readback) = hvm_lang::term::net_to_term::net_to_term_non_linear(&net, book);
 
   (res_term.to_string(&book.def_names), valid_readback)
 }
 
-pub fn hvm_lang_normal(book: &mut DefinitionBook, size: usize) -> (run::Net, Net, HashMap<run::Val, DefId>) {
+pub fn hvm_lang_normal(book: &mut DefinitionBook, size: usize) -> (run::Net, Net, std::collections::HashMap<run::Val, DefId>) {
   let (compiled, id_map) = hvm_lang::compile_book(book).unwrap();
   let (root, res_lnet) = normal(compiled, size);
   (root, res_lnet, id_map)
@@ -48,15 +75,15 @@ pub fn hvm_lang_normal(book: &mut DefinitionBook, size: usize) -> (run::Net, Net
 
 #[allow(unused_variables)]
 pub fn normal(book: Book, size: usize) -> (run::Net, Net) {
-  fn normal_cpu(book: run::Book, size: usize) -> run::Net {
+  fn normal_cpu(book: &run::Book, size: usize) -> run::Net {
     let mut rnet = run::Net::new(size);
-    rnet.boot(name_to_val("main"));
-    rnet.normal(&book);
+    rnet.boot(book.id("main").unwrap());
+    rnet.normal(book).unwrap();
     rnet
   }
 
   #[cfg(feature = "cuda")]
-  fn normal_gpu(book: run::Book) -> run::Net {
+  fn normal_gpu(book: &run::Book) -> run::Net {
     let (_, host_net) = hvmc::cuda::host::run_on_gpu(&book, "main").unwrap();
     host_net.to_runtime_net()
   }
@@ -66,14 +93,14 @@ pub fn normal(book: Book, size: usize) -> (run::Net, Net) {
   let rnet = {
     #[cfg(not(feature = "cuda"))]
     {
-      normal_cpu(book, size)
+      normal_cpu(&book, size)
     }
     #[cfg(feature = "cuda")]
     {
-      normal_gpu(book)
+      normal_gpu(&book)
     }
   };
 
-  let net = net_from_runtime(&rnet);
+  let net = net_from_runtime(&rnet, &book);
   (rnet, net)
 }
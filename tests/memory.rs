@@ -0,0 +1,147 @@
+use hvmc::ast::{net_from_runtime, show_net};
+use hvmc::run::{self, OutOfMemory};
+use loaders::*;
+
+mod loaders;
+
+fn boot(book: &run::Book, size: usize, cap: Option<usize>) -> run::Net {
+  let mut net = run::Net::new(size);
+  net.cap = cap;
+  net.boot(book.id("main").unwrap());
+  net
+}
+
+// Checks that a net stopped by the memory cap reaches the same normal form once the cap is lifted.
+fn assert_resumes(cap: usize, reduce: impl Fn(&mut run::Net) -> Result<(), OutOfMemory>) {
+  let book = sum_rec();
+  let mut net = boot(&book, 1 << 16, Some(cap));
+  let err = reduce(&mut net).unwrap_err();
+  assert_eq!(err.size, cap);
+  assert!(!net.rdex.is_empty());
+  net.cap = None;
+  reduce(&mut net).unwrap();
+
+  let mut rnet = boot(&book, 1 << 16, None);
+  rnet.normal(&book).unwrap();
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), show_net(&net_from_runtime(&rnet, &book)));
+}
+
+#[test]
+fn test_small_heap() {
+  let book = sum_rec();
+  let mut net = boot(&book, 64, None);
+  let err = net.normal(&book).unwrap_err();
+  assert_eq!(err.size, 64);
+  assert!(err.used > 0 && err.used <= 64);
+  assert_eq!(err.suggested_size(), Some(128));
+  assert_eq!(err.to_string(), format!("out of memory: {} of 64 nodes in use (try a size of 128 nodes)", err.used));
+}
+
+#[test]
+fn test_max_heap() {
+  // A heap can't grow past MAX_SIZE, so there's no size to suggest.
+  let err = OutOfMemory { used: run::MAX_SIZE, size: run::MAX_SIZE };
+  assert_eq!(err.suggested_size(), None);
+  assert_eq!(err.to_string(), format!("out of memory: {0} of {0} nodes in use (the limit of {0} nodes was reached)", run::MAX_SIZE));
+  // Nor past it when almost there.
+  assert_eq!(OutOfMemory { used: 1, size: run::MAX_SIZE - 1 }.suggested_size(), Some(run::MAX_SIZE));
+}
+
+#[test]
+fn test_cap() {
+  let book = sum_rec();
+  let mut net = boot(&book, 1 << 16, Some(64));
+  let err = net.normal(&book).unwrap_err();
+  assert_eq!(err.size, 64);
+  assert!(err.used <= 64);
+}
+
+#[test]
+fn test_cap_normal() {
+  let book = sum_rec();
+  assert_resumes(64, |net| net.normal(&book));
+}
+
+#[test]
+fn test_cap_fuel() {
+  let book = sum_rec();
+  assert_resumes(64, |net| {
+    while net.reduce_with_fuel(&book, 10)? == run::Status::OutOfFuel {}
+    Ok(())
+  });
+}
+
+#[test]
+fn test_cap_lazy() {
+  let book = sum_rec();
+  assert_resumes(24, |net| net.normal_lazy(&book));
+}
+
+#[test]
+fn test_cap_par() {
+  let book = sum_rec();
+  assert_resumes(64, |net| net.normal_par(&book, 4));
+}
+
+#[test]
+fn test_heap_grows() {
+  let book = sum_rec();
+  let mut net = boot(&book, 1 << 28, None);
+  assert_eq!(net.heap.len, run::SEG0);
+  net.normal(&book).unwrap();
+  assert!(net.heap.len > run::SEG0 && net.heap.len < 1 << 16);
+  let mut rnet = run::Net::new(1 << 16);
+  rnet.heap.grow_to(1 << 16);
+  rnet.boot(book.id("main").unwrap());
+  rnet.normal(&book).unwrap();
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), show_net(&net_from_runtime(&rnet, &book)));
+}
+
+#[test]
+fn test_compact() {
+  // Stops halfway, with nodes scattered across the heap, compacts, and continues.
+  let book = sum_rec();
+  let mut net = boot(&book, 1 << 16, None);
+  for _ in 0 .. 20 {
+    net.reduce_with_fuel(&book, 100).unwrap();
+  }
+  let before = show_net(&net_from_runtime(&net, &book));
+  let next = net.next;
+  net.compact();
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), before);
+  assert!(net.next < next);
+  for i in net.next .. net.heap.len {
+    assert!(net.heap.get(i as run::Val, run::P1).is_nil() && net.heap.get(i as run::Val, run::P2).is_nil());
+  }
+  net.normal(&book).unwrap();
+
+  let mut rnet = boot(&book, 1 << 16, None);
+  rnet.normal(&book).unwrap();
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), show_net(&net_from_runtime(&rnet, &book)));
+  assert_eq!(net.rewrites(), rnet.rewrites());
+}
+
+#[test]
+fn test_compact_shrinks() {
+  let book = sum_rec();
+  let mut net = boot(&book, 1 << 28, None);
+  net.normal(&book).unwrap();
+  assert!(net.heap.len > run::SEG0);
+  net.compact();
+  assert_eq!(net.heap.len, run::SEG0);
+  assert_eq!(net.next, 1);
+  assert_eq!(show_net(&net_from_runtime(&net, &book)), "#1024");
+}
+
+#[test]
+fn test_to_def_reduced() {
+  // The reduced net has holes, which `to_def` used to stop at.
+  let book = sum_rec();
+  let mut net = boot(&book, 1 << 16, None);
+  for _ in 0 .. 20 {
+    net.reduce_with_fuel(&book, 100).unwrap();
+  }
+  let before = show_net(&net_from_runtime(&net, &book));
+  let def = net.to_def();
+  assert_eq!(show_net(&net_from_runtime(&run::Net::from_def(def), &book)), before);
+}
@@ -0,0 +1,68 @@
+use hvmc::ast::{book_from_runtime, book_to_runtime, show_book, show_net};
+use hvmc::{codegen, run};
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+#[test]
+fn test_long_names() {
+  // Names that don't fit in a pointer, and that only differ at the end, are different defs.
+  let code = "
+    @Main.List.sum.go = (* #1)
+    @Main.List.sum.gone = (* #2)
+    @main = (a b) & @Main.List.sum.go ~ (* a) & @Main.List.sum.gone ~ (* b)
+  ";
+  let (_, net) = normal(parse_core(code), 16);
+  assert_snapshot!(show_net(&net), @"(#1 #2)");
+  // Readback shows refs by their names.
+  let (_, net) = normal(parse_core("@Main.List.sum.go = *\n@main = (@Main.List.sum.go *)"), 16);
+  assert_snapshot!(show_net(&net), @"(@Main.List.sum.go *)");
+}
+
+#[test]
+fn test_dense_ids() {
+  // Defs get the first ids, in name order, and undefined refs the ones after them.
+  let book = book_to_runtime(&parse_core("@b = @c\n@a = @b"), run::call_native());
+  assert_eq!(book.names, ["a", "b", "c"]);
+  assert_eq!((book.id("a"), book.id("b"), book.id("c"), book.id("d")), (Some(0), Some(1), Some(2), None));
+  assert_eq!(book.name(1), "b");
+  assert_eq!(book.name(7), "7");
+}
+
+#[test]
+fn test_book_round_trip() {
+  let code = "@Main.List.sum = (a a)\n@main = a\n& @Main.List.sum ~ (#1 a)\n";
+  let book = book_to_runtime(&parse_core(code), run::call_native());
+  assert_eq!(show_book(&book_from_runtime(&book)), code);
+  // Checkpoints keep the names.
+  let mut buf = vec![];
+  book.save(&mut buf).unwrap();
+  let loaded = run::Book::load(&mut &buf[..]).unwrap();
+  assert_eq!(loaded.names, book.names);
+  assert_eq!(show_book(&book_from_runtime(&loaded)), code);
+}
+
+#[test]
+fn test_idents() {
+  // Compiled defs are named by identifiers that don't clash.
+  let mut book = run::Book::new();
+  let names = ["a.b", "a_b", "a__b", "a_db", "Main.List.sum.go", "π"];
+  for name in names {
+    book.intern(name);
+  }
+  let idents: Vec<_> = (0 .. names.len() as run::Val).map(|id| codegen::ident(&book, id)).collect();
+  assert_eq!(idents, ["a_db", "a__b", "a____b", "a__db", "Main_dList_dsum_dgo", "_u3c0_"]);
+}
+
+#[test]
+fn test_book_size() {
+  // The defs table only grows up to the highest defined id.
+  assert!(run::Book::new().defs.is_empty());
+  let book = book_to_runtime(&parse_core("@a = *\n@b = *\n@main = (@a @nope)"), run::call_native());
+  assert_eq!(book.defs.len(), 3);
+  // Undefined refs are past the table, and aren't expanded.
+  assert_eq!(book.id("nope"), Some(3));
+  let (_, net) = normal(parse_core("@a = *\n@main = (@a @nope)"), 16);
+  assert_eq!(show_net(&net), "(@a @nope)");
+}
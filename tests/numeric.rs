@@ -4,7 +4,7 @@ mod loaders;
 mod numeric_tests {
   use crate::loaders::*;
   use hvmc::{
-    ast::{show_net, Book},
+    ast::{self, show_net, Book},
     run::{self, Tag},
   };
   use insta::{assert_debug_snapshot, assert_snapshot};
@@ -13,6 +13,17 @@ mod numeric_tests {
     parse_core(&format!("@main = root & <#{lnum} <#{rnum} root>> ~ #{op}"))
   }
 
+  // Reduces an operation written with the source syntax for numbers and operations.
+  fn eval_op(lnum: &str, op: &str, rnum: &str) -> String {
+    let net = parse_core(&format!("@main = root & <#{lnum} <#{rnum} root>> ~ #{op}"));
+    let (_rnet, net) = normal(net, 16);
+    show_net(&net)
+  }
+
+  fn num(val: run::IVal) -> String {
+    format!("#{}", run::unsigned(val))
+  }
+
   #[test]
   fn test_add() {
     let net = op_net(10, run::ADD, 2);
@@ -99,13 +110,22 @@ mod numeric_tests {
   }
 
   #[test]
+  #[cfg(not(feature = "ptr64"))]
   fn test_not() {
     let net = op_net(0, run::NOT, 256);
     let (rnet, net) = normal(net, 16);
-    assert_snapshot!(show_net(&net), @"#16776959");
+    assert_snapshot!(show_net(&net), @"#8388351");
     assert_debug_snapshot!(rnet.rewrites(), @"5");
   }
 
+  #[test]
+  #[cfg(feature = "ptr64")]
+  fn test_not_ptr64() {
+    let net = op_net(0, run::NOT, 256);
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#36028797018963711");
+  }
+
   #[test]
   fn test_lsh() {
     let net = op_net(10, run::LSH, 2);
@@ -121,15 +141,158 @@ mod numeric_tests {
   }
 
   #[test]
-  /// Division by zero always return the value of 0xFFFFFF,
-  /// that is read as the unsigned integer `16777215`
+  #[cfg(not(feature = "ptr64"))]
+  /// Division by zero always return the value of 0x7FFFFF,
+  /// that is read as the unsigned integer `8388607`
   fn test_div_by_0() {
     let net = op_net(9, run::DIV, 0);
     let (rnet, net) = normal(net, 16);
-    assert_snapshot!(show_net(&net), @"#16777215");
+    assert_snapshot!(show_net(&net), @"#8388607");
     assert_debug_snapshot!(rnet.rewrites(), @"5");
   }
 
+  #[test]
+  #[cfg(feature = "ptr64")]
+  fn test_div_by_0_ptr64() {
+    let net = op_net(9, run::DIV, 0);
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#36028797018963967");
+  }
+
+  #[test]
+  // Arithmetic wraps at the width of a number, which depends on the pointer width.
+  fn test_wrap() {
+    let net = parse_core(&format!("@main = root & <#{} <#1 root>> ~ #{}", run::NUM_MASK, run::ADD));
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#0");
+  }
+
+  #[test]
+  #[cfg(feature = "ptr64")]
+  fn test_wide_mul() {
+    let net = parse_core(&format!("@main = root & <#16777216 <#16777216 root>> ~ #{}", run::MUL));
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#281474976710656");
+  }
+
+  #[test]
+  fn test_num_too_large() {
+    let code = format!("#{}", run::NUM_MASK as u128 + 1);
+    let err = ast::do_parse_tree(&code).unwrap_err().msg;
+    assert_eq!(err, format!("Number {} doesn't fit in {} bits", run::NUM_MASK as u128 + 1, run::NUM_BITS));
+  }
+
+  #[test]
+  fn test_mod_by_0() {
+    assert_eq!(eval_op("9", "%", "0"), "#9");
+    assert_eq!(eval_op("9", "%s", "0"), "#9");
+    assert_eq!(eval_op("9", "/s", "0"), num(-1));
+  }
+
+  #[test]
+  fn test_lte_gte() {
+    assert_eq!(eval_op("2", "<=", "2"), "#1");
+    assert_eq!(eval_op("3", "<=", "2"), "#0");
+    assert_eq!(eval_op("2", ">=", "3"), "#0");
+    assert_eq!(eval_op("3", ">=", "2"), "#1");
+  }
+
+  #[test]
+  fn test_op_symbols() {
+    for (sym, op) in ast::OPS {
+      let (_rnet, net) = normal(op_net(10, op, 2), 16);
+      assert_eq!(eval_op("10", sym, "2"), show_net(&net), "{}", sym);
+    }
+  }
+
+  #[test]
+  fn test_signed() {
+    assert_eq!(eval_op("-7", "+", "2"), num(-5));
+    assert_eq!(eval_op("-7", "*", "-2"), "#14");
+    assert_eq!(eval_op("-7", "/s", "2"), num(-3));
+    assert_eq!(eval_op("-7", "%s", "2"), num(-1));
+    assert_eq!(eval_op("7", "%s", "-2"), "#1");
+    assert_eq!(eval_op("-1", "<s", "0"), "#1");
+    assert_eq!(eval_op("-1", "<", "0"), "#0");
+    assert_eq!(eval_op("-1", ">s", "-2"), "#1");
+    assert_eq!(eval_op("-2", "<=s", "-3"), "#0");
+    assert_eq!(eval_op("-3", ">=s", "-3"), "#1");
+    assert_eq!(eval_op("-8", ">>s", "1"), num(-4));
+    assert_eq!(eval_op("-8", ">>s", "100"), num(-1));
+    assert_eq!(eval_op("-8", ">>", "1"), format!("#{}", run::unsigned(-8) >> 1));
+  }
+
+  #[test]
+  // Dividing the smallest number by -1 overflows back to it.
+  fn test_signed_overflow() {
+    let min = -(1 << (run::NUM_BITS - 1));
+    assert_eq!(eval_op(&min.to_string(), "/s", "-1"), num(min));
+    assert_eq!(eval_op(&min.to_string(), "%s", "-1"), "#0");
+    let err = ast::do_parse_tree(&format!("#{}", min - 1)).unwrap_err().msg;
+    assert_eq!(err, format!("Number {} doesn't fit in {} bits", min - 1, run::NUM_BITS));
+  }
+
+  #[test]
+  fn test_shift_by_width() {
+    assert_eq!(eval_op("1", "<<", "100"), "#0");
+    assert_eq!(eval_op("5", ">>", "100"), "#0");
+  }
+
+  #[test]
+  fn test_float_literals() {
+    for code in ["#1.5", "#-2.25", "#0.0", "#1e20", "#inf", "#-inf", "#NaN", "#3"] {
+      let tree = ast::do_parse_tree(code).unwrap();
+      let shown = ast::show_tree(&tree);
+      assert_eq!(ast::do_parse_tree(&shown).unwrap(), tree, "{}", code);
+    }
+    let show = |code: &str| ast::show_tree(&ast::do_parse_tree(code).unwrap());
+    assert_eq!(show("#1.5"), "#1.5");
+    assert_eq!(show("#-2.25"), "#-2.25");
+    assert_eq!(show("#1e0"), "#1.0");
+    assert_eq!(show("#3"), "#3");
+    let err = ast::do_parse_tree("#1.5x").unwrap_err().msg;
+    assert_eq!(err, "Expected a number, found '1.5x'");
+  }
+
+  #[test]
+  fn test_float_ops() {
+    assert_eq!(eval_op("1.5", "+", "2.25"), "#3.75");
+    assert_eq!(eval_op("1.5", "-", "2.25"), "#-0.75");
+    assert_eq!(eval_op("1.5", "*", "-2.0"), "#-3.0");
+    assert_eq!(eval_op("1.0", "/", "0.0"), "#inf");
+    assert_eq!(eval_op("7.5", "%", "2.0"), "#1.5");
+    assert_eq!(eval_op("-1.5", "<", "0.5"), "#1");
+    assert_eq!(eval_op("1.5", ">=", "1.5"), "#1");
+    assert_eq!(eval_op("1.5", "==", "1.25"), "#0");
+    // Rounded to a float that fits in a number.
+    let sum = ast::do_parse_tree(&eval_op("0.1", "+", "0.2")).unwrap();
+    let ast::Tree::Num { val } = sum else { unreachable!() };
+    assert!(run::is_float(val) && (run::to_float(val) - 0.3).abs() < 1e-3);
+  }
+
+  #[test]
+  fn test_float_mixed() {
+    // The left operand is read the way the right one is.
+    assert_eq!(eval_op("2", "+", "1.5"), "#1.5");
+    assert_eq!(eval_op("1.5", "+", "2"), format!("#{}", (run::from_float(1.5) & run::NUM_MASK) + 2));
+    // A float isn't an operation.
+    let (_rnet, net) = normal(parse_core("@main = root & <#2 root> ~ #1.5"), 16);
+    assert_eq!(show_net(&net), "#0");
+  }
+
+  #[test]
+  fn test_float_conversions() {
+    assert_eq!(eval_op("0", &run::I2F.to_string(), "-3"), "#-3.0");
+    assert_eq!(eval_op("0", &run::F2I.to_string(), "-3.75"), num(-3));
+    assert_eq!(eval_op("0", &run::F2I.to_string(), "2.5"), "#2");
+  }
+
+  #[test]
+  fn test_unknown_op() {
+    let err = ast::do_parse_tree("#<>").unwrap_err().msg;
+    assert_eq!(err, "Unknown operation '<>'");
+  }
+
   #[test]
   // TODO: we lack a way to check if it's actually doing the chained ops optimization, or if it's doing one op per interaction
   fn test_chained_ops() {
@@ -0,0 +1,92 @@
+use hvmc::ast::{book_to_runtime, net_from_runtime, show_net, Book};
+use hvmc::run;
+use loaders::*;
+
+mod loaders;
+
+// Checks that many threads reach the same normal form as the sequential runtime.
+fn assert_normal_par(book: Book, size: usize, same_rewrites: bool) {
+  let book = book_to_runtime(&book, run::call_native());
+  let normal_par = |threads| {
+    let mut net = run::Net::new(size);
+    net.boot(book.id("main").unwrap());
+    net.normal_par(&book, threads).unwrap();
+    net
+  };
+  let rnet = normal_par(1);
+  let net = show_net(&net_from_runtime(&rnet, &book));
+  for threads in [2, 3, 4, 8] {
+    let par_rnet = normal_par(threads);
+    assert_eq!(show_net(&net_from_runtime(&par_rnet, &book)), net, "threads: {threads}");
+    if same_rewrites {
+      assert_eq!(par_rnet.rewrites(), rnet.rewrites(), "threads: {threads}");
+    }
+  }
+}
+
+#[test]
+fn test_par_commutation() {
+  let book = parse_core("@main = root & (x x) ~ [* root]");
+  assert_normal_par(book, 1 << 10, true);
+}
+
+#[test]
+fn test_par_bool_and() {
+  let book = parse_core(
+    "
+    @true = (b (* b))
+    @fals = (* (b b))
+    @and  = ((b (@fals c)) (b c))
+    @main = root & @and ~ (@true (@fals root))
+  ",
+  );
+  assert_normal_par(book, 1 << 10, true);
+}
+
+#[test]
+fn test_par_church() {
+  let book = load_core("examples/church.hvmc", &[]);
+  assert_normal_par(book, 1 << 12, true);
+}
+
+#[test]
+fn test_par_big_labels() {
+  let book = load_core("examples/church.hvmc", &[("{2 ", "{1002 "), ("{3 ", "{1003 ")]);
+  assert_normal_par(book, 1 << 12, true);
+}
+
+#[test]
+fn test_par_church_exp() {
+  let book = load_core("benches/programs/church/church_exp.hvmc", &[]);
+  assert_normal_par(book, 1 << 16, true);
+}
+
+#[test]
+fn test_par_num_match() {
+  let book = load_core("examples/num_match.hvmc", &[]);
+  assert_normal_par(book, 1 << 10, true);
+}
+
+#[test]
+fn test_par_sum_rec() {
+  let book = load_core("examples/sum_rec.hvmc", &[("#24", "#12")]);
+  assert_normal_par(book, 1 << 16, false);
+}
+
+#[test]
+fn test_par_sum_tree() {
+  let book = load_core("examples/sum_tree.hvmc", &[("#22", "#10")]);
+  assert_normal_par(book, 1 << 16, false);
+}
+
+#[test]
+fn test_par_dec_bits_tree() {
+  let book = load_core("benches/programs/binary-counter/dec_bits_tree.hvmc", &[]);
+  assert_normal_par(book, 1 << 18, true);
+}
+
+#[test]
+fn test_par_alloc_big_tree() {
+  let book = load_core("benches/programs/tree/alloc_big_tree.hvmc", &[]);
+  assert_normal_par(book, 1 << 18, true);
+}
@@ -0,0 +1,57 @@
+use hvmc::ast::{do_parse_book, do_parse_net, do_parse_tree, show_net};
+use insta::assert_snapshot;
+
+#[test]
+fn test_parse_error_span() {
+  let code = "@main = a\n  & (a b ~ *\n";
+  let err = do_parse_book(code).unwrap_err();
+  assert_eq!(err.msg, "Expected ')', found '~'");
+  assert_eq!(err.expected, vec!["')'"]);
+  assert_eq!(&code[err.span.clone()], "~");
+  assert_eq!(err.line_col(code), (2, 10));
+}
+
+#[test]
+fn test_parse_error_render() {
+  let code = "@id = (a a)\n@main = (@id #1x)\n";
+  let err = do_parse_book(code).unwrap_err();
+  assert_snapshot!(err.render(code, "main.hvmc"), @r###"
+  error: Expected a number, found '1x'
+   --> main.hvmc:2:15
+    |
+  2 | @main = (@id #1x)
+    |               ^^
+  "###);
+}
+
+#[test]
+fn test_parse_end_of_input() {
+  let err = do_parse_book("@main = (a").unwrap_err();
+  assert_eq!(err.msg, "Expected a tree, found end of input");
+  assert_eq!(err.span, 10 .. 10);
+}
+
+#[test]
+fn test_parse_trailing_garbage() {
+  let err = do_parse_book("@main = (a a) )").unwrap_err();
+  assert_eq!(err.msg, "Expected '&' or '@' or end of input, found ')'");
+  assert_eq!(err.expected, vec!["'&'", "'@'", "end of input"]);
+  let err = do_parse_book("main = *").unwrap_err();
+  assert_eq!(err.msg, "Expected '@' or end of input, found 'm'");
+  let err = do_parse_net("(a a) & * ~ * *").unwrap_err();
+  assert_eq!(err.msg, "Expected end of input, found '*'");
+  assert_eq!(err.span, 14 .. 15);
+  let err = do_parse_tree("#1 // one\n)").unwrap_err();
+  assert_eq!(err.line_col("#1 // one\n)"), (2, 1));
+}
+
+#[test]
+fn test_parse_ok() {
+  // Comments and whitespace are allowed anywhere between trees.
+  let net = do_parse_net(" (a b) // root\n & @foo ~ (a b) // redex\n").unwrap();
+  assert_snapshot!(show_net(&net), @r###"
+  (a b)
+  & @foo ~ (a b)
+  "###);
+  assert!(do_parse_book("").unwrap().is_empty());
+}
@@ -0,0 +1,61 @@
+use hvmc::ast::{inline_book, pre_reduce_book, pre_reduce_net, show_net};
+use insta::assert_snapshot;
+use loaders::*;
+
+mod loaders;
+
+fn pre_reduce(code: &str) -> String {
+  let book = pre_reduce_book(&parse_core(code), 1 << 16);
+  show_net(&book["k"])
+}
+
+#[test]
+fn test_pre_reduce_closed() {
+  assert_snapshot!(pre_reduce("@k = (x y) & (#1 #2) ~ (x y)"), @"(#1 #2)");
+  // #1 is addition.
+  assert_snapshot!(pre_reduce("@k = r & #1 ~ <#2 <#3 r>>"), @"#5");
+  assert_snapshot!(pre_reduce("@k = (a b) & (* b) ~ (#7 a)"), @"(a a)");
+}
+
+#[test]
+fn test_pre_reduce_held() {
+  // Refs aren't expanded, even when the redexes around them are reduced.
+  assert_snapshot!(pre_reduce("@k = (a b) & @k ~ (c d) & (c d) ~ (a b)"), @r###"
+  (a b)
+  & @k ~ (a b)
+  "###);
+  // Neither are operations on a number that isn't known yet.
+  assert_snapshot!(pre_reduce("@k = (a (b r)) & #1 ~ <a <b r>>"), @r###"
+  (a (b c))
+  & #1 ~ <a <b c>>
+  "###);
+  // Nor are pairs the runtime has no rule for.
+  assert_snapshot!(pre_reduce("@f = *\n@k = (x y) & @f ~ #1 & (#2 #3) ~ (x y)"), @r###"
+  (#2 #3)
+  & @f ~ #1
+  "###);
+  // But erasing a ref is fine.
+  assert_snapshot!(pre_reduce("@k = * & @k ~ *"), @"*");
+}
+
+#[test]
+fn test_pre_reduce_limits() {
+  // A def that would grow is kept as it is.
+  let code = "@k = (a (b (c d))) & {2 a b} ~ (c d)";
+  assert_eq!(pre_reduce(code), show_net(&parse_core(code)["k"]));
+  // So is one that takes too long.
+  let book = parse_core("@k = (x y) & (#1 #2) ~ (x y)");
+  assert!(pre_reduce_net(&book["k"], 0).is_none());
+  assert_eq!(pre_reduce_book(&book, 0), book);
+}
+
+#[test]
+fn test_pre_reduce_church() {
+  // Pre-reducing, after inlining, doesn't change the result, and saves rewrites.
+  let path = format!("{}/examples/church.hvmc", env!("CARGO_MANIFEST_DIR"));
+  let book = parse_core(&std::fs::read_to_string(path).unwrap());
+  let (rnet, net) = normal(book.clone(), 1 << 12);
+  let (reduced_rnet, reduced_net) = normal(pre_reduce_book(&inline_book(&book, &["main"], 32), 1 << 16), 1 << 12);
+  assert_eq!(show_net(&reduced_net), show_net(&net));
+  assert!(reduced_rnet.rewrites() <= rnet.rewrites());
+}
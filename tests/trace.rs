@@ -0,0 +1,193 @@
+use hvmc::ast::book_to_runtime;
+use hvmc::run::{self, Kind, Observer, Ptr, Val};
+use loaders::*;
+use std::sync::{Arc, Mutex};
+
+mod loaders;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Event {
+  Interact(Kind, Ptr, Ptr),
+  Call(String, Vec<Val>),
+  Link(Ptr, Ptr),
+}
+
+// Records every event, in a log shared with the test, with the book's names.
+struct Record(Arc<Mutex<Vec<Event>>>, Vec<String>);
+
+impl Observer for Record {
+  fn interact(&mut self, kind: Kind, a: Ptr, b: Ptr) {
+    self.0.lock().unwrap().push(Event::Interact(kind, a, b));
+  }
+
+  fn call(&mut self, id: Val, locs: &[Val]) {
+    self.0.lock().unwrap().push(Event::Call(self.1[id as usize].clone(), locs.to_vec()));
+  }
+
+  fn link(&mut self, a: Ptr, b: Ptr) {
+    self.0.lock().unwrap().push(Event::Link(a, b));
+  }
+}
+
+fn trace(code: &str, lazy: bool) -> (run::Net, Vec<Event>) {
+  let book = book_to_runtime(&parse_core(code), run::call_native());
+  let log = Arc::new(Mutex::new(vec![]));
+  let mut net = run::Net::new(1 << 12);
+  net.observer = Some(Box::new(Record(log.clone(), book.names.clone())));
+  net.boot(book.id("main").unwrap());
+  if lazy {
+    net.normal_lazy(&book).unwrap();
+  } else {
+    net.normal(&book).unwrap();
+  }
+  let events = log.lock().unwrap().clone();
+  (net, events)
+}
+
+fn kinds(events: &[Event]) -> Vec<Kind> {
+  events.iter().filter_map(|event| if let Event::Interact(kind, ..) = event { Some(*kind) } else { None }).collect()
+}
+
+#[test]
+fn test_trace_interactions() {
+  let (net, events) = trace("@main = root & (x x) ~ [* root]", false);
+  // The erasure of two erasers is skipped when linking, so it isn't an interaction.
+  assert_eq!(kinds(&events), [Kind::Comm, Kind::Eras, Kind::Anni]);
+  assert_eq!((net.comm, net.eras, net.anni), (1, 2, 1));
+  let Event::Interact(_, a, b) = events.iter().find(|event| matches!(event, Event::Interact(..))).unwrap().clone() else {
+    unreachable!()
+  };
+  assert_eq!((a.tag(), b.tag()), (run::CT0, run::CT1));
+  assert_eq!((a.val(), b.val()), (1, 2));
+}
+
+#[test]
+fn test_trace_calls() {
+  let (net, events) = trace("@id = (x x)  @main = r & @id ~ (#7 r)", false);
+  let calls: Vec<_> = events.iter().filter(|event| matches!(event, Event::Call(..))).cloned().collect();
+  assert_eq!(calls, [Event::Call("main".to_string(), vec![1]), Event::Call("id".to_string(), vec![2])]);
+  assert_eq!(calls.len(), net.dref);
+  // Defs get dense ids in name order, so @id is 0.
+  assert!(events.contains(&Event::Interact(Kind::Dref, Ptr::new(run::REF, 0), Ptr::new(run::CT0, 1))));
+  assert!(events.iter().any(|event| matches!(event, Event::Link(a, _) if *a == Ptr::new(run::NUM, 7))));
+}
+
+#[test]
+fn test_trace_lazy() {
+  let (_, events) = trace("@main = root & (x x) ~ [* root]", true);
+  let mut kinds = kinds(&events);
+  kinds.sort_by_key(|kind| kind.name());
+  assert_eq!(kinds, [Kind::Anni, Kind::Comm, Kind::Eras]);
+}
+
+#[test]
+fn test_profile() {
+  let book = sum_rec();
+  let log = Arc::new(Mutex::new(vec![]));
+  let profile = Arc::new(Mutex::new(run::Profile::new()));
+  let mut net = run::Net::new(1 << 16);
+  net.observer = Some(Box::new((Record(log.clone(), book.names.clone()), profile.clone())));
+  net.boot(book.id("main").unwrap());
+  net.normal(&book).unwrap();
+
+  let profile = profile.lock().unwrap();
+  let top: Vec<_> = profile.top(2).into_iter().map(|(id, cost)| (book.name(id), cost.calls, cost.nodes)).collect();
+  assert_eq!(top, [("sum".to_string(), 2047, 6141), ("sumS".to_string(), 1023, 6138)]);
+  assert_eq!(profile.defs.values().map(|cost| cost.calls).sum::<usize>(), net.dref);
+  // Every interaction is on a node owned by some def, and at most two.
+  let interactions = kinds(&log.lock().unwrap()).len();
+  let rewrites: usize = profile.defs.values().map(|cost| cost.rewrites).sum();
+  assert!(interactions <= rewrites && rewrites <= 2 * interactions);
+}
+
+// A JSON value, as written by '--trace'.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+  Num(f64),
+  Str(String),
+  Arr(Vec<Json>),
+  Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+  fn get(&self, key: &str) -> &Json {
+    let Json::Obj(fields) = self else { panic!("{:?} isn't an object", self) };
+    &fields.iter().find(|(k, _)| k == key).unwrap_or_else(|| panic!("no '{}' in {:?}", key, self)).1
+  }
+}
+
+// Parses a JSON value from the start of some text, returning it and the rest.
+fn parse_json(txt: &str) -> (Json, &str) {
+  let txt = txt.trim_start();
+  let (head, rest) = txt.split_at(1);
+  match head {
+    "\"" => {
+      let mut out = String::new();
+      let mut chars = rest.char_indices();
+      while let Some((i, c)) = chars.next() {
+        match c {
+          '"' => return (Json::Str(out), &rest[i + 1 ..]),
+          '\\' => match chars.next().unwrap().1 {
+            'u' => {
+              let hex: String = (0 .. 4).map(|_| chars.next().unwrap().1).collect();
+              out.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+            }
+            c => out.push(c),
+          },
+          c => out.push(c),
+        }
+      }
+      panic!("unterminated string");
+    }
+    "[" | "{" => {
+      let (close, mut rest) = (if head == "[" { "]" } else { "}" }, rest.trim_start());
+      let mut items = vec![];
+      while !rest.starts_with(close) {
+        let key = if head == "{" {
+          let (Json::Str(key), after) = parse_json(rest) else { panic!("expected a key") };
+          rest = after.trim_start().strip_prefix(':').unwrap();
+          key
+        } else {
+          String::new()
+        };
+        let (val, after) = parse_json(rest);
+        items.push((key, val));
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+      }
+      let rest = &rest[1 ..];
+      if head == "[" {
+        (Json::Arr(items.into_iter().map(|(_, val)| val).collect()), rest)
+      } else {
+        (Json::Obj(items), rest)
+      }
+    }
+    _ => {
+      let end = txt.find(|c: char| !c.is_ascii_digit() && !"-+.eE".contains(c)).unwrap_or(txt.len());
+      (Json::Num(txt[.. end].parse().unwrap()), &txt[end ..])
+    }
+  }
+}
+
+#[test]
+fn test_trace_cli() {
+  let path = std::env::temp_dir().join(format!("hvmc-test-{}.trace", std::process::id()));
+  run_cli("run", "@id = (x x)\n@main = r & @id ~ (#7 r)", &["--trace", path.to_str().unwrap()]).unwrap();
+  let trace = std::fs::read_to_string(&path).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  let events: Vec<Json> = trace.lines().map(|line| {
+    let (json, rest) = parse_json(line);
+    assert!(rest.is_empty(), "trailing text in {}", line);
+    json
+  }).collect();
+  let str = |txt: &str| Json::Str(txt.to_string());
+  let calls: Vec<_> = events.iter().filter(|event| *event.get("event") == str("call")).collect();
+  assert_eq!(calls.iter().map(|call| call.get("ref").clone()).collect::<Vec<_>>(), [str("main"), str("id")]);
+  assert_eq!(*calls[1].get("locs"), Json::Arr(vec![Json::Num(2.0)]));
+  let dref = events.iter().find(|event| *event.get("event") == str("interact")).unwrap();
+  assert_eq!(*dref.get("kind"), str("dref"));
+  assert_eq!(*dref.get("a").get("ref"), str("id"));
+  assert_eq!(*dref.get("b").get("tag"), str("CT0"));
+  let seven = Json::Obj(vec![("tag".to_string(), str("NUM")), ("num".to_string(), Json::Num(7.0))]);
+  assert!(events.iter().any(|event| *event.get("event") == str("link") && *event.get("a") == seven));
+}